@@ -4,9 +4,26 @@ use std::io::Write;
 use std::path::PathBuf;
 use std::process::Command;
 
+#[path = "build/svd.rs"]
+mod svd;
+
 fn main() {
     let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
 
+    // ----------------------
+    // Register definitions, see src/pac
+    let svd_file = "svd/rp2350.svd";
+    let svd = std::fs::read_to_string(svd_file).expect("failed to read the SVD file");
+    std::fs::write(out.join("pac.rs"), svd::generate(&svd)).unwrap();
+
+    println!("cargo:rerun-if-changed={}", svd_file);
+    println!("cargo:rerun-if-changed=build/svd.rs");
+
+    // Host builds (unit tests) don't link a firmware image
+    if !env::var("TARGET").unwrap().starts_with("thumb") {
+        return;
+    }
+
     // ----------------------
     // memory.x handling
     File::create(out.join("memory.x"))
@@ -0,0 +1,495 @@
+//! Register definitions generator
+//!
+//! Reads a CMSIS-SVD file and emits one module per peripheral, written with the
+//! `register_block!`, `register!` and `field_values!` macros of `src/registers`,
+//! plus one constant per peripheral instance. Peripherals `derivedFrom` another one
+//! share its module, and numbered registers with the same layout (`GPIO0_CTRL`,
+//! `GPIO1_CTRL`, ...) are folded into a register array (`gpio_ctrl(n)`).
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use roxmltree::Node;
+
+struct Peripheral {
+    name: String,
+    group: Option<String>,
+    description: Option<String>,
+    base: u64,
+    derived_from: Option<String>,
+    registers: Vec<Register>,
+}
+
+#[derive(Clone)]
+struct Register {
+    name: String,
+    description: Option<String>,
+    offset: u64,
+    reset: u32,
+    /// (count, stride) for register arrays
+    dim: Option<(u64, u64)>,
+    fields: Vec<Field>,
+}
+
+#[derive(Clone, PartialEq)]
+struct Field {
+    name: String,
+    description: Option<String>,
+    offset: u8,
+    width: u8,
+    values: Vec<EnumValue>,
+}
+
+#[derive(Clone, PartialEq)]
+struct EnumValue {
+    name: String,
+    description: Option<String>,
+    value: u32,
+}
+
+/// Register properties inherited from the device and peripheral levels
+#[derive(Clone, Copy)]
+struct Defaults {
+    reset: u32,
+}
+
+/// Generates the Rust source for every peripheral of `svd`
+pub fn generate(svd: &str) -> String {
+    let doc = roxmltree::Document::parse(svd).expect("invalid SVD file");
+    let device = doc.root_element();
+    let defaults = Defaults { reset: number(device, "resetValue").unwrap_or(0) as u32 };
+
+    let peripherals: Vec<Peripheral> = child(device, "peripherals")
+        .expect("SVD file without peripherals")
+        .children()
+        .filter(|n| n.has_tag_name("peripheral"))
+        .map(|n| parse_peripheral(n, defaults))
+        .collect();
+
+    let modules = module_names(&peripherals);
+
+    let mut out = String::new();
+    for p in peripherals.iter().filter(|p| p.derived_from.is_none()) {
+        emit_module(&mut out, p, &modules[&p.name]);
+    }
+    for p in &peripherals {
+        let base = p.derived_from.as_deref().unwrap_or(&p.name);
+        let base_peripheral = peripherals.iter().find(|q| q.name == base)
+            .unwrap_or_else(|| panic!("{} is derived from unknown peripheral {base}", p.name));
+        let module = &modules[base];
+        let block = camel_case(module);
+        let description = p.description.as_ref().or(base_peripheral.description.as_ref());
+        let _ = writeln!(out);
+        emit_doc(&mut out, "", description.map_or(&format!("`{}` peripheral", p.name), |d| d));
+        let _ = writeln!(
+            out,
+            "pub const {}: {module}::{block} = {module}::{block}::at({});",
+            p.name.to_uppercase(),
+            hex(p.base)
+        );
+    }
+    out
+}
+
+fn parse_peripheral(node: Node, defaults: Defaults) -> Peripheral {
+    let defaults = Defaults { reset: number(node, "resetValue").map_or(defaults.reset, |v| v as u32) };
+    let mut registers = Vec::new();
+    if let Some(regs) = child(node, "registers") {
+        parse_registers(regs, "", 0, defaults, &mut registers);
+    }
+    Peripheral {
+        name: text(node, "name").expect("peripheral without a name"),
+        group: text(node, "groupName"),
+        description: text(node, "description"),
+        base: number(node, "baseAddress").expect("peripheral without a base address"),
+        derived_from: node.attribute("derivedFrom").map(str::to_owned),
+        registers,
+    }
+}
+
+/// Collects the registers under `node`, flattening clusters into `CLUSTER_REGISTER` names
+fn parse_registers(node: Node, prefix: &str, base: u64, defaults: Defaults, out: &mut Vec<Register>) {
+    for n in node.children().filter(Node::is_element) {
+        let defaults = Defaults { reset: number(n, "resetValue").map_or(defaults.reset, |v| v as u32) };
+        let offset = base + number(n, "addressOffset").unwrap_or(0);
+        let name = text(n, "name").expect("register without a name");
+        assert!(n.attribute("derivedFrom").is_none(), "derived register {name} is not supported");
+
+        match n.tag_name().name() {
+            "cluster" => match dim(n) {
+                Some((count, stride)) => {
+                    for i in 0..count {
+                        let name = name.replace("[%s]", "").replace("%s", "");
+                        let prefix = format!("{prefix}{name}{i}_");
+                        parse_registers(n, &prefix, offset + i * stride, defaults, out);
+                    }
+                }
+                None => parse_registers(n, &format!("{prefix}{name}_"), offset, defaults, out),
+            },
+            "register" => {
+                let dim = dim(n);
+                let name = match dim {
+                    Some(_) => name.replace("[%s]", "").replace("%s", ""),
+                    None => name,
+                };
+                out.push(Register {
+                    name: format!("{prefix}{name}"),
+                    description: text(n, "description"),
+                    offset,
+                    reset: defaults.reset,
+                    dim,
+                    fields: child(n, "fields").map(parse_fields).unwrap_or_default(),
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_fields(node: Node) -> Vec<Field> {
+    let mut fields: Vec<Field> = node
+        .children()
+        .filter(|n| n.has_tag_name("field"))
+        .map(|n| {
+            let (offset, width) = bit_range(n);
+            let values = child(n, "enumeratedValues")
+                .map(|e| {
+                    e.children()
+                        .filter(|v| v.has_tag_name("enumeratedValue") && number(*v, "value").is_some())
+                        .map(|v| EnumValue {
+                            name: text(v, "name").expect("enumerated value without a name"),
+                            description: text(v, "description"),
+                            value: number(v, "value").unwrap() as u32,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            Field {
+                name: text(n, "name").expect("field without a name"),
+                description: text(n, "description"),
+                offset,
+                width,
+                values,
+            }
+        })
+        .collect();
+    fields.sort_by_key(|f| std::cmp::Reverse(f.offset));
+    fields
+}
+
+/// Field position from any of the three SVD notations, as (offset, width)
+fn bit_range(node: Node) -> (u8, u8) {
+    if let Some(range) = text(node, "bitRange") {
+        let (msb, lsb) = range
+            .trim_matches(|c| c == '[' || c == ']')
+            .split_once(':')
+            .expect("malformed bitRange");
+        let (msb, lsb): (u8, u8) = (msb.parse().unwrap(), lsb.parse().unwrap());
+        return (lsb, msb - lsb + 1);
+    }
+    if let (Some(lsb), Some(msb)) = (number(node, "lsb"), number(node, "msb")) {
+        return (lsb as u8, (msb - lsb + 1) as u8);
+    }
+    let offset = number(node, "bitOffset").expect("field without a position");
+    (offset as u8, number(node, "bitWidth").unwrap_or(1) as u8)
+}
+
+fn dim(node: Node) -> Option<(u64, u64)> {
+    Some((number(node, "dim")?, number(node, "dimIncrement").expect("dim without dimIncrement")))
+}
+
+/// Module name of every peripheral that isn't derived from another one: its group name when
+/// no other peripheral shares it, its own name otherwise
+fn module_names(peripherals: &[Peripheral]) -> BTreeMap<String, String> {
+    let bases: Vec<&Peripheral> = peripherals.iter().filter(|p| p.derived_from.is_none()).collect();
+    bases
+        .iter()
+        .map(|p| {
+            let unique_group = p.group.as_ref().filter(|g| {
+                bases.iter().filter(|q| q.group.as_ref() == Some(*g)).count() == 1
+            });
+            let name = unique_group.unwrap_or(&p.name).to_lowercase();
+            (p.name.clone(), name)
+        })
+        .collect()
+}
+
+/// Splits a register name around its last number: `GPIO12_CTRL` -> (`GPIO`, 12, `_CTRL`)
+fn split_index(name: &str) -> Option<(&str, u64, &str)> {
+    let end = name.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = name[..end].rfind(|c: char| !c.is_ascii_digit()).map_or(0, |i| i + 1);
+    if start == 0 {
+        return None;
+    }
+    Some((&name[..start], name[start..end].parse().ok()?, &name[end..]))
+}
+
+/// Folds numbered registers with the same layout into register arrays
+fn fold_arrays(registers: &[Register]) -> Vec<Register> {
+    let mut groups: BTreeMap<(&str, &str), Vec<(u64, &Register)>> = BTreeMap::new();
+    for r in registers.iter().filter(|r| r.dim.is_none()) {
+        if let Some((prefix, index, suffix)) = split_index(&r.name) {
+            groups.entry((prefix, suffix)).or_default().push((index, r));
+        }
+    }
+
+    let names: BTreeSet<&str> = registers.iter().map(|r| r.name.as_str()).collect();
+    let mut folded: BTreeMap<&str, Register> = BTreeMap::new();
+    let mut members: BTreeSet<&str> = BTreeSet::new();
+    for ((prefix, suffix), mut regs) in groups {
+        regs.sort_by_key(|(index, _)| *index);
+        let name = format!("{prefix}{suffix}");
+        if let Some(array) = fold(prefix, &name, &regs).filter(|_| !names.contains(name.as_str())) {
+            members.extend(regs.iter().map(|(_, r)| r.name.as_str()));
+            folded.insert(regs[0].1.name.as_str(), array);
+        }
+    }
+
+    registers
+        .iter()
+        .filter_map(|r| match folded.remove(r.name.as_str()) {
+            Some(array) => Some(array),
+            None if members.contains(r.name.as_str()) => None,
+            None => Some(r.clone()),
+        })
+        .collect()
+}
+
+/// Builds the register array for `regs` (sorted by index), if they can be one
+fn fold(prefix: &str, name: &str, regs: &[(u64, &Register)]) -> Option<Register> {
+    if regs.len() < 2 || regs.iter().enumerate().any(|(i, (index, _))| *index != i as u64) {
+        return None;
+    }
+    let first = regs[0].1;
+    let stride = regs[1].1.offset.checked_sub(first.offset).filter(|s| *s > 0)?;
+    let evenly_spaced = regs.iter().enumerate().all(|(i, (_, r))| r.offset == first.offset + i as u64 * stride);
+    let same_reset = regs.iter().all(|(_, r)| r.reset == first.reset);
+    if !evenly_spaced || !same_reset {
+        return None;
+    }
+
+    // Field names may repeat the register number (`CLK_GPOUT0_SELECTED`), compare them without it
+    let layouts: Vec<Vec<Field>> = regs
+        .iter()
+        .map(|(index, r)| {
+            r.fields
+                .iter()
+                .map(|f| Field { name: f.name.replace(&format!("{prefix}{index}"), prefix), ..f.clone() })
+                .collect()
+        })
+        .collect();
+    let same_shape = layouts.iter().all(|fields| {
+        fields.len() == layouts[0].len()
+            && fields.iter().zip(&layouts[0]).all(|(a, b)| (&a.name, a.offset, a.width) == (&b.name, b.offset, b.width))
+    });
+
+    // Fields that differ per register are left out, enumerated values that differ are dropped
+    let fields = if same_shape {
+        layouts[0]
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                let same_values = layouts.iter().all(|fields| fields[i].values == f.values);
+                Field { values: if same_values { f.values.clone() } else { Vec::new() }, ..f.clone() }
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let same_description = regs.iter().all(|(_, r)| r.description == first.description);
+    let range = format!("`{}` to `{}`", first.name, regs[regs.len() - 1].1.name);
+    let description = match (&first.description, same_description) {
+        (Some(d), true) => format!("{d}\n\n{range}"),
+        _ => range,
+    };
+
+    Some(Register {
+        name: name.to_owned(),
+        description: Some(description),
+        offset: first.offset,
+        reset: first.reset,
+        dim: Some((regs.len() as u64, stride)),
+        fields,
+    })
+}
+
+fn emit_module(out: &mut String, p: &Peripheral, module: &str) {
+    let block = camel_case(module);
+    let registers = fold_arrays(&p.registers);
+    let mut types: BTreeSet<String> = registers.iter().map(|r| register_type(r, &block)).collect();
+
+    let _ = writeln!(out);
+    emit_doc(out, "", p.description.as_ref().map_or(&format!("`{}` registers", p.name), |d| d));
+    let _ = writeln!(out, "pub mod {module} {{");
+
+    let _ = writeln!(out, "    crate::register_block! {{");
+    emit_doc(out, "        ", &format!("`{}` register block", p.name));
+    let _ = writeln!(out, "        pub {block} {{");
+    for r in &registers {
+        if let Some(d) = &r.description {
+            emit_doc(out, "            ", d);
+        }
+        let dim = r.dim.map(|(count, stride)| format!("[{count}; {}]", hex(stride))).unwrap_or_default();
+        let _ = writeln!(
+            out,
+            "            {} => {}{dim}: {},",
+            hex(r.offset),
+            identifier(&r.name.to_lowercase(), &["at", "base"]),
+            register_type(r, &block)
+        );
+    }
+    let _ = writeln!(out, "        }}");
+    let _ = writeln!(out, "    }}");
+
+    for r in &registers {
+        let ty = register_type(r, &block);
+        let mut fields = String::new();
+        for f in &r.fields {
+            if let Some(d) = &f.description {
+                emit_doc(&mut fields, "            ", d);
+            }
+            let name = identifier(&f.name.to_lowercase(), &["bits", "bit_at", "field_at"]);
+            let kind = if f.width == 1 && f.values.is_empty() {
+                format!("bit({})", f.offset)
+            } else {
+                format!("bits({}, {})", f.offset, f.width)
+            };
+            let values = if f.values.is_empty() {
+                String::new()
+            } else {
+                let mut values = format!("{ty}{}", camel_case(&f.name));
+                if !types.insert(values.clone()) {
+                    values.push_str("Values");
+                    types.insert(values.clone());
+                }
+                emit_values(out, &values, f);
+                format!(" as {values}")
+            };
+            let _ = writeln!(fields, "            {name}: {kind}{values},");
+        }
+
+        let _ = writeln!(out);
+        let _ = writeln!(out, "    crate::register! {{");
+        if let Some(d) = &r.description {
+            emit_doc(out, "        ", d);
+        }
+        let _ = writeln!(out, "        pub {ty} (reset = {}) {{", hex(r.reset as u64));
+        out.push_str(&fields);
+        let _ = writeln!(out, "        }}");
+        let _ = writeln!(out, "    }}");
+    }
+    let _ = writeln!(out, "}}");
+}
+
+fn emit_values(out: &mut String, name: &str, field: &Field) {
+    let _ = writeln!(out);
+    let _ = writeln!(out, "    crate::field_values! {{");
+    emit_doc(out, "        ", &format!("Values of the `{}` field", field.name));
+    let _ = writeln!(out, "        pub enum {name}: {} {{", field.width);
+    let mut seen = BTreeSet::new();
+    for v in &field.values {
+        let mut variant = variant_name(&v.name);
+        while !seen.insert(variant.clone()) {
+            variant.push('_');
+        }
+        if let Some(d) = &v.description {
+            emit_doc(out, "            ", d);
+        }
+        let _ = writeln!(out, "            {variant} = {},", hex(v.value as u64));
+    }
+    let _ = writeln!(out, "        }}");
+    let _ = writeln!(out, "    }}");
+}
+
+/// Doc attributes for `text`, one line per paragraph with blank lines in between
+fn emit_doc(out: &mut String, indent: &str, text: &str) {
+    for (i, paragraph) in text.split("\n\n").enumerate() {
+        if i > 0 {
+            let _ = writeln!(out, "{indent}#[doc = \"\"]");
+        }
+        let line = paragraph.split_whitespace().collect::<Vec<_>>().join(" ");
+        let _ = writeln!(out, "{indent}#[doc = {line:?}]");
+    }
+}
+
+/// Type of a register's layout, renamed if it clashes with the register block
+fn register_type(register: &Register, block: &str) -> String {
+    let ty = camel_case(&register.name);
+    if ty == block { format!("{ty}Reg") } else { ty }
+}
+
+/// `CLK_REF_CTRL` -> `ClkRefCtrl`
+fn camel_case(name: &str) -> String {
+    let ty: String = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first).chain(chars.map(|c| c.to_ascii_lowercase())).collect::<String>()
+        })
+        .collect();
+    if ty.starts_with(|c: char| c.is_ascii_digit()) { format!("_{ty}") } else { ty }
+}
+
+/// `xosc_clksrc` -> `XoscClksrc`, names starting with a digit keep their separators: `1_15MHZ` -> `_1_15mhz`
+fn variant_name(name: &str) -> String {
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name.to_lowercase().replace(|c: char| !c.is_ascii_alphanumeric(), "_"))
+    } else {
+        camel_case(name)
+    }
+}
+
+/// Makes `name` a valid method name that doesn't shadow one of `reserved`
+fn identifier(name: &str, reserved: &[&str]) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false",
+        "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+        "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+        "abstract", "become", "box", "do", "final", "gen", "macro", "override", "priv", "try", "typeof",
+        "unsized", "virtual", "yield",
+    ];
+    let name = name.replace(|c: char| !c.is_ascii_alphanumeric() && c != '_', "_");
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else if KEYWORDS.contains(&name.as_str()) || reserved.contains(&name.as_str()) {
+        format!("{name}_")
+    } else {
+        name
+    }
+}
+
+/// Hex literal with `_` every 4 digits above 16 bits: `0x4001_0000`
+fn hex(value: u64) -> String {
+    let digits = format!("{value:x}");
+    if digits.len() <= 4 {
+        return format!("0x{digits}");
+    }
+    let padded = format!("{value:0width$x}", width = digits.len().div_ceil(4) * 4);
+    let groups: Vec<&str> = padded.as_bytes().chunks(4).map(|c| std::str::from_utf8(c).unwrap()).collect();
+    format!("0x{}", groups.join("_"))
+}
+
+fn child<'a, 'i>(node: Node<'a, 'i>, tag: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|n| n.has_tag_name(tag))
+}
+
+fn text(node: Node, tag: &str) -> Option<String> {
+    child(node, tag).and_then(|n| n.text()).map(|t| t.trim().to_owned())
+}
+
+/// Parses SVD's scaled non-negative integers: decimal, `0x` hex or `#` binary
+fn number(node: Node, tag: &str) -> Option<u64> {
+    let value = text(node, tag)?;
+    let value = value.to_lowercase();
+    let parsed = if let Some(hex) = value.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(bin) = value.strip_prefix('#').or_else(|| value.strip_prefix("0b")) {
+        u64::from_str_radix(bin, 2)
+    } else {
+        value.parse()
+    };
+    Some(parsed.unwrap_or_else(|_| panic!("malformed number in <{tag}>: {value}")))
+}
@@ -0,0 +1,43 @@
+//! Register access backends
+//!
+//! Every register access in the crate goes through [`reg_read`](crate::reg_read) and
+//! [`reg_write`](crate::reg_write), which dispatch to [`ActiveBackend`].
+//! On the target this is the volatile [`Mmio`] backend, on the host it is the
+//! simulated memory map in [`sim`], so driver logic can run under `cargo test`.
+
+#[cfg(not(target_os = "none"))]
+pub mod sim;
+
+use core::ptr;
+
+/// A way of reading and writing 32 bits registers by absolute address
+pub trait RegisterBackend {
+    /// Reads the register at `addr`
+    fn read(addr: usize) -> u32;
+
+    /// Writes `val` to the register at `addr`
+    fn write(addr: usize, val: u32);
+}
+
+/// Volatile memory mapped IO, the real hardware
+pub struct Mmio;
+
+impl RegisterBackend for Mmio {
+    #[inline(always)]
+    fn read(addr: usize) -> u32 {
+        unsafe { ptr::read_volatile(addr as *const u32) }
+    }
+
+    #[inline(always)]
+    fn write(addr: usize, val: u32) {
+        unsafe { ptr::write_volatile(addr as *mut u32, val) }
+    }
+}
+
+/// The backend used by [`reg_read`](crate::reg_read) and [`reg_write`](crate::reg_write)
+#[cfg(target_os = "none")]
+pub type ActiveBackend = Mmio;
+
+/// The backend used by [`reg_read`](crate::reg_read) and [`reg_write`](crate::reg_write)
+#[cfg(not(target_os = "none"))]
+pub type ActiveBackend = sim::Simulated;
@@ -0,0 +1,319 @@
+//! Simulated RP2350 memory map for host side tests
+//!
+//! Registers are plain words in a per-thread map, so every test thread gets its own chip.
+//! Writes to the peripheral address space honour the atomic XOR/SET/CLEAR aliases
+//! at +0x1000/+0x2000/+0x3000, and a few registers whose value depends on other
+//! registers (reset done flags, SIO set/clear/xor registers, spinlocks, clock muxes,
+//! PLL lock, frequency counter status, GPIO edge acknowledgement) are modeled with hooks.
+//! Tests can install their own hooks for anything else.
+
+extern crate std;
+
+use core::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::backend::RegisterBackend;
+use crate::clocks::{generator, Clock};
+use crate::pac::{CLOCKS, IO_BANK0, IO_QSPI, PLL_SYS, PLL_USB, RESETS, SIO};
+use crate::{ATOMIC_CLEAR, ATOMIC_SET, ATOMIC_XOR};
+
+/// Called instead of a plain load, gets the address and the stored word
+pub type ReadHook = fn(addr: usize, stored: u32) -> u32;
+
+/// Called instead of a plain store, gets the address and the value after alias resolution
+pub type WriteHook = fn(addr: usize, val: u32);
+
+/// APB and AHB peripherals, where bits [13:12] of the address select the atomic alias
+const ALIASED: core::ops::Range<usize> = 0x4000_0000..0x6000_0000;
+const ALIAS_MASK: usize = 0x3000;
+
+/// Value of RESETS_RESET out of power on: everything held in reset
+const RESETS_RESET_POR: u32 = 0x1fff_ffff;
+
+const RESETS_RESET: usize = RESETS.reset().addr();
+const RESETS_RESET_DONE: usize = RESETS.reset_done().addr();
+
+const SIO_BASE: usize = SIO.base();
+
+/// Values of a PLL's CS and PWR out of power on: REFDIV = 1, everything powered down
+const PLL_CS_POR: u32 = 0x1;
+const PLL_PWR_POR: u32 = 0x2d;
+/// FC0_STATUS of a finished count: DONE and PASS
+const FC0_STATUS_DONE: u32 = 0x11;
+
+/// PLL_PWR bits that must be clear for the VCO to lock: PD and VCOPD
+const PLL_PWR_VCO: u32 = 0x21;
+
+/// Edge bits of the GPIO raw interrupt registers, 4 events per pin with the edges on top
+const IO_INTR_EDGES: u32 = 0xcccc_cccc;
+
+// SIO registers with set/clear/xor companions, as (register, set, clear, xor) offsets
+const SIO_ATOMIC_GROUPS: [(usize, usize, usize, usize); 4] = [
+    (0x010, 0x018, 0x020, 0x028), // GPIO_OUT
+    (0x014, 0x01c, 0x024, 0x02c), // GPIO_HI_OUT
+    (0x030, 0x038, 0x040, 0x048), // GPIO_OE
+    (0x034, 0x03c, 0x044, 0x04c), // GPIO_HI_OE
+];
+const SIO_SPINLOCK0: usize = SIO_BASE + 0x100;
+const SIO_SPINLOCK_COUNT: usize = 32;
+
+struct Memory {
+    words: BTreeMap<usize, u32>,
+    read_hooks: BTreeMap<usize, ReadHook>,
+    write_hooks: BTreeMap<usize, WriteHook>,
+}
+
+impl Memory {
+    fn new() -> Self {
+        let mut mem = Self {
+            words: BTreeMap::new(),
+            read_hooks: BTreeMap::new(),
+            write_hooks: BTreeMap::new(),
+        };
+        mem.install_chip_models();
+        mem
+    }
+
+    /// Registers whose behaviour every driver relies on
+    fn install_chip_models(&mut self) {
+        self.words.insert(RESETS_RESET, RESETS_RESET_POR);
+        self.read_hooks.insert(RESETS_RESET_DONE, |_, _| !peek(RESETS_RESET) & RESETS_RESET_POR);
+
+        for (_, set, clr, xor) in SIO_ATOMIC_GROUPS {
+            self.write_hooks.insert(SIO_BASE + set, sio_atomic_write);
+            self.write_hooks.insert(SIO_BASE + clr, sio_atomic_write);
+            self.write_hooks.insert(SIO_BASE + xor, sio_atomic_write);
+        }
+
+        for n in 0..SIO_SPINLOCK_COUNT {
+            self.read_hooks.insert(SIO_SPINLOCK0 + n * 4, spinlock_read);
+            self.write_hooks.insert(SIO_SPINLOCK0 + n * 4, spinlock_write);
+        }
+
+        for clock in Clock::ALL {
+            let gen = generator(clock);
+            self.read_hooks.insert(gen.ctrl().addr(), clock_ctrl_read);
+            self.read_hooks.insert(gen.selected().addr(), clock_selected_read);
+        }
+
+        for pll in [PLL_SYS, PLL_USB] {
+            self.words.insert(pll.cs().addr(), PLL_CS_POR);
+            self.words.insert(pll.pwr().addr(), PLL_PWR_POR);
+            self.read_hooks.insert(pll.cs().addr(), pll_cs_read);
+        }
+
+        // The frequency counter finishes at once, tests provide FC0_RESULT
+        self.read_hooks.insert(CLOCKS.fc0_status().addr(), |_, _| FC0_STATUS_DONE);
+
+        for reg in 0..6 {
+            self.write_hooks.insert(IO_BANK0.intr(reg).addr(), io_intr_write);
+        }
+        self.write_hooks.insert(IO_QSPI.intr().addr(), io_intr_write);
+    }
+}
+
+std::thread_local! {
+    static MEMORY: RefCell<Memory> = RefCell::new(Memory::new());
+}
+
+/// SIO set/clear/xor registers update their base register
+fn sio_atomic_write(addr: usize, val: u32) {
+    let offset = addr - SIO_BASE;
+    for (reg, set, clr, xor) in SIO_ATOMIC_GROUPS {
+        let current = peek(SIO_BASE + reg);
+        let new = match offset {
+            o if o == set => current | val,
+            o if o == clr => current & !val,
+            o if o == xor => current ^ val,
+            _ => continue,
+        };
+        poke(SIO_BASE + reg, new);
+    }
+}
+
+/// Reading a free spinlock claims it and returns its bit, reading a claimed one returns 0
+fn spinlock_read(addr: usize, stored: u32) -> u32 {
+    if stored != 0 {
+        return 0;
+    }
+    poke(addr, 1);
+    1 << ((addr - SIO_SPINLOCK0) / 4)
+}
+
+/// Writing any value to a spinlock releases it
+fn spinlock_write(addr: usize, _val: u32) {
+    poke(addr, 0);
+}
+
+/// A clock generator reports ENABLED as soon as it's enabled
+fn clock_ctrl_read(_addr: usize, stored: u32) -> u32 {
+    let enable = (stored >> 11) & 1;
+    (stored & !(1 << 28)) | (enable << 28)
+}
+
+/// Clock muxes switch at once: glitchless ones select their SRC, the others have a single input
+fn clock_selected_read(addr: usize, _stored: u32) -> u32 {
+    let ctrl = addr - 0x8;
+    if ctrl == generator(Clock::Ref).ctrl().addr() {
+        1 << (peek(ctrl) & 0x3)
+    } else if ctrl == generator(Clock::Sys).ctrl().addr() {
+        1 << (peek(ctrl) & 0x1)
+    } else {
+        1
+    }
+}
+
+/// A PLL locks as soon as its VCO is powered up
+fn pll_cs_read(addr: usize, stored: u32) -> u32 {
+    // PWR follows CS in the PLL's register block
+    let locked = peek(addr + 0x4) & PLL_PWR_VCO == 0;
+    (stored & !(1 << 31)) | ((locked as u32) << 31)
+}
+
+/// Writing 1 to a latched edge clears it, levels follow the pin and ignore writes
+fn io_intr_write(addr: usize, val: u32) {
+    poke(addr, peek(addr) & !(val & IO_INTR_EDGES));
+}
+
+/// Resolves an atomic alias address into the register address and the value to store
+fn resolve_alias(addr: usize, val: u32) -> (usize, u32) {
+    if !ALIASED.contains(&addr) {
+        return (addr, val);
+    }
+    let base = addr & !ALIAS_MASK;
+    let current = peek(base);
+    let new = match addr & ALIAS_MASK {
+        ATOMIC_XOR => current ^ val,
+        ATOMIC_SET => current | val,
+        ATOMIC_CLEAR => current & !val,
+        _ => val,
+    };
+    (base, new)
+}
+
+/// Strips the atomic alias bits from a peripheral address
+fn base_address(addr: usize) -> usize {
+    if ALIASED.contains(&addr) { addr & !ALIAS_MASK } else { addr }
+}
+
+/// Backend reading and writing the simulated memory map of the current thread
+pub struct Simulated;
+
+impl RegisterBackend for Simulated {
+    fn read(addr: usize) -> u32 {
+        let addr = base_address(addr);
+        let (stored, hook) = MEMORY.with_borrow(|mem| {
+            (mem.words.get(&addr).copied().unwrap_or(0), mem.read_hooks.get(&addr).copied())
+        });
+        match hook {
+            Some(hook) => hook(addr, stored),
+            None => stored,
+        }
+    }
+
+    fn write(addr: usize, val: u32) {
+        let (addr, val) = resolve_alias(addr, val);
+        let hook = MEMORY.with_borrow(|mem| mem.write_hooks.get(&addr).copied());
+        match hook {
+            Some(hook) => hook(addr, val),
+            None => poke(addr, val),
+        }
+    }
+}
+
+/// Restores the simulated chip of the current thread to its power on state
+pub fn reset() {
+    MEMORY.with_borrow_mut(|mem| *mem = Memory::new());
+}
+
+/// Serializes the tests holding the guard
+///
+/// Each test thread has its own chip, but driver state outside of it (cached clock frequencies,
+/// clock listeners, GPIO ownership) is shared: tests that assert on it hold this guard for their whole body.
+pub fn exclusive() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Reads the stored word at `addr`, bypassing hooks and aliases
+///
+/// `addr`: register's memory address
+pub fn peek(addr: usize) -> u32 {
+    MEMORY.with_borrow(|mem| mem.words.get(&addr).copied().unwrap_or(0))
+}
+
+/// Stores `val` at `addr`, bypassing hooks and aliases
+///
+/// `addr`: register's memory address
+/// `val`: value to store
+pub fn poke(addr: usize, val: u32) {
+    MEMORY.with_borrow_mut(|mem| {
+        mem.words.insert(addr, val);
+    });
+}
+
+/// Installs a hook that computes the value read from `addr`
+///
+/// `addr`: register's memory address
+/// `hook`: replaces the plain load
+pub fn on_read(addr: usize, hook: ReadHook) {
+    MEMORY.with_borrow_mut(|mem| {
+        mem.read_hooks.insert(addr, hook);
+    });
+}
+
+/// Installs a hook that handles writes to `addr`
+///
+/// `addr`: register's memory address
+/// `hook`: replaces the plain store
+pub fn on_write(addr: usize, hook: WriteHook) {
+    MEMORY.with_borrow_mut(|mem| {
+        mem.write_hooks.insert(addr, hook);
+    });
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::*;
+    use crate::{reg_read, reg_write};
+
+    const REG: usize = 0x4001_8048;
+
+    #[test]
+    fn test_atomic_aliases() {
+        reset();
+        reg_write(REG, 0b1100);
+        reg_write(REG + ATOMIC_SET, 0b0011);
+        assert_eq!(reg_read(REG), 0b1111);
+        reg_write(REG + ATOMIC_CLEAR, 0b0101);
+        assert_eq!(reg_read(REG), 0b1010);
+        reg_write(REG + ATOMIC_XOR, 0b1111);
+        assert_eq!(reg_read(REG), 0b0101);
+    }
+
+    #[test]
+    fn test_sio_has_no_aliases() {
+        reset();
+        reg_write(SIO_BASE + 0x018, 0b101);
+        reg_write(SIO_BASE + 0x028, 0b011);
+        assert_eq!(peek(SIO_BASE + 0x010), 0b110);
+        assert_eq!(peek(SIO_BASE + 0x018 + ATOMIC_SET), 0);
+    }
+
+    #[test]
+    fn test_reset_done_follows_reset() {
+        reset();
+        assert_eq!(reg_read(RESETS_RESET_DONE), 0);
+        reg_write(RESETS_RESET + ATOMIC_CLEAR, 1 << 6);
+        assert_eq!(reg_read(RESETS_RESET_DONE) as u32, 1 << 6);
+    }
+
+    #[test]
+    fn test_read_hook() {
+        reset();
+        on_read(REG, |_, stored| stored + 1);
+        poke(REG, 41);
+        assert_eq!(reg_read(REG), 42);
+    }
+}
@@ -0,0 +1,195 @@
+//! Board level helpers
+//!
+//! Pico 2 boards have a single user input, the BOOTSEL button, which pulls the flash chip
+//! select low through a resistor. [`bootsel_pressed`] reads it between two flash accesses.
+//!
+//! ```ignore
+//! if board::bootsel_pressed() {
+//!     led.set();
+//! }
+//! ```
+
+use crate::gpio::qspi::QspiPin;
+use crate::pac::io_qspi::GpioQspiSsCtrlOeover;
+use crate::pac::{IO_QSPI, SIO};
+
+/// QSPI SS in the high SIO registers
+const SS_MASK: u32 = QspiPin::Ss.mask();
+
+/// Address of the SS control register
+const SS_CTRL: usize = IO_QSPI.gpio_qspi_ss_ctrl().addr();
+
+/// Address of the high SIO inputs
+const GPIO_HI_IN: usize = SIO.gpio_hi_in().addr();
+
+/// OEOVER field of the SS control register
+const OEOVER_MASK: u32 = 0b11 << 14;
+
+/// OEOVER set to disable the output
+const OEOVER_DISABLE: u32 = (GpioQspiSsCtrlOeover::Disable as u32) << 14;
+
+/// Iterations given to SS to settle through the button's resistor once released
+const SETTLE_LOOPS: u32 = 1000;
+
+/// Reads a register with a single `ldr`
+///
+/// `core::ptr::read_volatile` is an ordinary function in unoptimised builds, called in flash.
+#[cfg(target_os = "none")]
+macro_rules! ram_read {
+    ($addr:expr) => {{
+        let val: u32;
+        unsafe {
+            core::arch::asm!(
+                "ldr {val}, [{addr}]",
+                addr = in(reg) $addr,
+                val = out(reg) val,
+                options(nostack, preserves_flags),
+            );
+        }
+        val
+    }};
+}
+
+/// Reads a register through the simulated memory map
+#[cfg(not(target_os = "none"))]
+macro_rules! ram_read {
+    ($addr:expr) => {
+        crate::reg_read($addr) as u32
+    };
+}
+
+/// Writes a register with a single `str`, see `ram_read`
+#[cfg(target_os = "none")]
+macro_rules! ram_write {
+    ($addr:expr, $val:expr) => {
+        unsafe {
+            core::arch::asm!(
+                "str {val}, [{addr}]",
+                addr = in(reg) $addr,
+                val = in(reg) $val,
+                options(nostack, preserves_flags),
+            );
+        }
+    };
+}
+
+/// Writes a register through the simulated memory map
+#[cfg(not(target_os = "none"))]
+macro_rules! ram_write {
+    ($addr:expr, $val:expr) => {
+        crate::reg_write($addr, $val as usize)
+    };
+}
+
+/// Spins `$loops` times in a register countdown, with no overflow check or call to fetch
+#[cfg(target_os = "none")]
+macro_rules! ram_delay {
+    ($loops:expr) => {
+        unsafe {
+            core::arch::asm!(
+                "2:",
+                "subs {n}, #1",
+                "bne 2b",
+                n = inout(reg) $loops => _,
+                options(nomem, nostack),
+            );
+        }
+    };
+}
+
+/// The host has nothing to wait for
+#[cfg(not(target_os = "none"))]
+macro_rules! ram_delay {
+    ($loops:expr) => {
+        let _ = $loops;
+    };
+}
+
+/// Whether the BOOTSEL button is pressed
+///
+/// The flash is unreachable while SS is sampled, so the function runs from RAM with interrupts
+/// masked on the calling core. The other core must not run from flash meanwhile.
+#[inline(never)]
+#[cfg_attr(target_os = "none", link_section = ".data.ram_func")]
+pub fn bootsel_pressed() -> bool {
+    // Nothing below may fetch from flash, even unoptimised: the register API and the core::ptr
+    // volatile accessors compile to calls there, so registers are reached with inline asm and
+    // the remaining helpers are `#[inline(always)]`
+    let masked = interrupts_mask();
+
+    // Release SS so only the button and the pull-up drive it
+    let ctrl = ram_read!(SS_CTRL);
+    ram_write!(SS_CTRL, (ctrl & !OEOVER_MASK) | OEOVER_DISABLE);
+
+    ram_delay!(SETTLE_LOOPS);
+    let pressed = ram_read!(GPIO_HI_IN) & SS_MASK == 0;
+
+    // Give SS back to the flash interface
+    ram_write!(SS_CTRL, ctrl);
+
+    interrupts_restore(masked);
+    pressed
+}
+
+/// Masks interrupts on the calling core, returns whether they already were
+#[cfg(target_arch = "arm")]
+#[inline(always)]
+fn interrupts_mask() -> bool {
+    let primask: u32;
+    unsafe {
+        core::arch::asm!("mrs {}, PRIMASK", "cpsid i", out(reg) primask, options(nomem, nostack));
+    }
+    primask & 1 != 0
+}
+
+/// The host has no interrupts to mask
+#[cfg(not(target_arch = "arm"))]
+#[inline(always)]
+fn interrupts_mask() -> bool {
+    true
+}
+
+/// Unmasks interrupts on the calling core, unless they were masked before [`interrupts_mask`]
+///
+/// `masked`: what [`interrupts_mask`] returned
+#[cfg(target_arch = "arm")]
+#[inline(always)]
+fn interrupts_restore(masked: bool) {
+    if !masked {
+        unsafe { core::arch::asm!("cpsie i", options(nomem, nostack)) };
+    }
+}
+
+/// The host has no interrupts to unmask
+#[cfg(not(target_arch = "arm"))]
+#[inline(always)]
+fn interrupts_restore(_masked: bool) {}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{bootsel_pressed, SS_MASK};
+    use crate::backend::sim;
+    use crate::pac::{IO_QSPI, SIO};
+
+    /// SS as sampled: low unless its output is disabled, the button decides then
+    fn sample_ss(_addr: usize, stored: u32) -> u32 {
+        let oeover = (sim::peek(IO_QSPI.gpio_qspi_ss_ctrl().addr()) >> 14) & 0b11;
+        assert_eq!(oeover, 0b10, "SS sampled while the flash drives it");
+        stored
+    }
+
+    #[test]
+    fn test_bootsel_overrides_and_restores_ss() {
+        sim::reset();
+        sim::poke(IO_QSPI.gpio_qspi_ss_ctrl().addr(), 0);
+        sim::on_read(SIO.gpio_hi_in().addr(), sample_ss);
+
+        sim::poke(SIO.gpio_hi_in().addr(), SS_MASK);
+        assert!(!bootsel_pressed());
+        sim::poke(SIO.gpio_hi_in().addr(), 0);
+        assert!(bootsel_pressed());
+
+        // The flash gets SS back as it was
+        assert_eq!(sim::peek(IO_QSPI.gpio_qspi_ss_ctrl().addr()), 0);
+    }
+}
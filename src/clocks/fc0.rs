@@ -0,0 +1,104 @@
+//! Frequency counter
+//!
+//! FC0 counts the edges of any internal clock source over a test interval timed by clk_ref,
+//! so it measures what the hardware actually runs at rather than what the registers imply.
+
+use crate::clocks::{clock_get_hz, Clock, Source};
+use crate::pac::clocks::Fc0SrcFc0Src;
+use crate::pac::CLOCKS;
+
+/// Test interval of 2^10 us, about 1 ms for a 1 kHz resolution
+const FC0_INTERVAL: u32 = 10;
+
+/// Bits of the fractional kHz part in FC0_RESULT
+const FC0_FRAC_BITS: u32 = 5;
+
+/// FC0 input selecting `source`
+fn fc0_src(source: Source) -> Fc0SrcFc0Src {
+    match source {
+        Source::PllSys => Fc0SrcFc0Src::PllSysClksrcPrimary,
+        Source::PllUsb => Fc0SrcFc0Src::PllUsbClksrcPrimary,
+        Source::PllUsbPrimaryRefOpcg => Fc0SrcFc0Src::PllUsbClksrcPrimaryDft,
+        Source::Rosc => Fc0SrcFc0Src::RoscClksrc,
+        Source::RoscPh => Fc0SrcFc0Src::RoscClksrcPh,
+        Source::Xosc => Fc0SrcFc0Src::XoscClksrc,
+        Source::Lposc => Fc0SrcFc0Src::LposcClksrc,
+        Source::Gpin0 => Fc0SrcFc0Src::ClksrcGpin0,
+        Source::Gpin1 => Fc0SrcFc0Src::ClksrcGpin1,
+        Source::ClkRef => Fc0SrcFc0Src::ClkRef,
+        Source::ClkSys => Fc0SrcFc0Src::ClkSys,
+        Source::ClkPeri => Fc0SrcFc0Src::ClkPeri,
+        Source::ClkHstx => Fc0SrcFc0Src::ClkHstx,
+        Source::ClkUsb => Fc0SrcFc0Src::ClkUsb,
+        Source::ClkAdc => Fc0SrcFc0Src::ClkAdc,
+        Source::OtpClk2fc => Fc0SrcFc0Src::OtpClk2fc,
+    }
+}
+
+/// Source carrying a generator's output, the GPOUT generators can't be counted
+pub const fn clock_source(clock: Clock) -> Option<Source> {
+    match clock {
+        Clock::Ref => Some(Source::ClkRef),
+        Clock::Sys => Some(Source::ClkSys),
+        Clock::Peri => Some(Source::ClkPeri),
+        Clock::Hstx => Some(Source::ClkHstx),
+        Clock::Usb => Some(Source::ClkUsb),
+        Clock::Adc => Some(Source::ClkAdc),
+        Clock::Gpout0 | Clock::Gpout1 | Clock::Gpout2 | Clock::Gpout3 => None,
+    }
+}
+
+/// Runs one count and returns the raw FC0_RESULT, kHz in 20.5 fixed point
+fn count(source: Source) -> u32 {
+    // A previous count may still be running
+    while CLOCKS.fc0_status().read().running() {}
+
+    CLOCKS.fc0_ref_khz().write(|w| w.fc0_ref_khz().bits((clock_get_hz(Clock::Ref) / 1000) as u32));
+    CLOCKS.fc0_interval().write(|w| w.fc0_interval().bits(FC0_INTERVAL));
+    CLOCKS.fc0_min_khz().write(|w| w.fc0_min_khz().bits(0));
+    CLOCKS.fc0_max_khz().write(|w| w.fc0_max_khz().bits(0x01ff_ffff));
+    // Selecting the source starts the count
+    CLOCKS.fc0_src().write(|w| w.fc0_src().variant(fc0_src(source)));
+
+    while !CLOCKS.fc0_status().read().done() {}
+    CLOCKS.fc0_result().read().bits()
+}
+
+/// Measures a clock source in kHz against clk_ref
+///
+/// The cached clk_ref frequency must be right, it's the counter's time base.
+///
+/// `source`: the source to measure
+pub fn measure_khz(source: Source) -> u32 {
+    count(source) >> FC0_FRAC_BITS
+}
+
+/// Measures a clock source in Hz against clk_ref, with the counter's 1/32 kHz resolution
+///
+/// `source`: the source to measure
+pub fn measure_hz(source: Source) -> usize {
+    let result = count(source) as usize;
+    (result * 1000) >> FC0_FRAC_BITS
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{measure_hz, measure_khz};
+    use crate::backend::sim;
+    use crate::clocks::{clock_set_reported_hz, Clock, Source, XOSC_HZ};
+    use crate::pac::clocks::Fc0SrcFc0Src;
+    use crate::pac::CLOCKS;
+
+    #[test]
+    fn test_measure_programs_counter() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        clock_set_reported_hz(Clock::Ref, XOSC_HZ);
+        // 48.5 MHz in 20.5 fixed point kHz
+        sim::poke(CLOCKS.fc0_result().addr(), (48_500 << 5) | 16);
+        assert_eq!(measure_khz(Source::PllUsb), 48_500);
+        assert_eq!(measure_hz(Source::PllUsb), 48_500_500);
+        assert_eq!(CLOCKS.fc0_ref_khz().read().fc0_ref_khz().bits(), 12_000);
+        assert_eq!(CLOCKS.fc0_src().read().fc0_src().variant(), Some(Fc0SrcFc0Src::PllUsbClksrcPrimary));
+    }
+}
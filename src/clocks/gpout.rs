@@ -0,0 +1,134 @@
+//! Clock outputs
+//!
+//! The four GPOUT generators route any clock source to a GPIO through the GPCK function,
+//! divided by a 16.16 fractional divider. Handy to check a PLL on a frequency counter or to
+//! clock an external chip.
+//!
+//! ```ignore
+//! let out = gpout::start(Gpout::Gpout0, 21, Source::PllSys, 10_000_000)?;
+//! ```
+
+use crate::clocks::{self, generator, Clock, ClockError, Source};
+use crate::gpio::{FunctionPin, Funcsel};
+
+/// The clock output generators
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Gpout {
+    Gpout0,
+    Gpout1,
+    Gpout2,
+    Gpout3,
+}
+
+impl Gpout {
+    /// The output's clock generator
+    pub const fn clock(self) -> Clock {
+        match self {
+            Gpout::Gpout0 => Clock::Gpout0,
+            Gpout::Gpout1 => Clock::Gpout1,
+            Gpout::Gpout2 => Clock::Gpout2,
+            Gpout::Gpout3 => Clock::Gpout3,
+        }
+    }
+
+    /// GPIOs the output can drive
+    pub const fn pins(self) -> &'static [usize] {
+        match self {
+            Gpout::Gpout0 => &[13, 21],
+            Gpout::Gpout1 => &[15, 23],
+            Gpout::Gpout2 => &[24],
+            Gpout::Gpout3 => &[25],
+        }
+    }
+}
+
+/// A running clock output, stopped and released from its GPIO once dropped
+pub struct GpoutPin {
+    output: Gpout,
+    hz: usize,
+    _pin: FunctionPin,
+}
+
+/// Routes a clock source to a GPIO
+///
+/// Returns [`ClockError::InvalidPin`] if the output can't drive `pin`, [`ClockError::PinTaken`]
+/// if someone owns it and [`ClockError::UnknownFrequency`] for the GPIN sources, whose frequency
+/// isn't known.
+///
+/// `output`: the clock output generator
+/// `pin`: one of [`Gpout::pins`]
+/// `source`: the source to output
+/// `freq_hz`: requested output frequency in Hz
+pub fn start(output: Gpout, pin: usize, source: Source, freq_hz: usize) -> Result<GpoutPin, ClockError> {
+    if !output.pins().contains(&pin) {
+        return Err(ClockError::InvalidPin);
+    }
+    let src_hz = clocks::source_hz(source).ok_or(ClockError::UnknownFrequency)?;
+    let pin = FunctionPin::claim(pin, Funcsel::Gpck).ok_or(ClockError::PinTaken)?;
+    let hz = clocks::configure(output.clock(), source, src_hz, freq_hz)?;
+    Ok(GpoutPin { output, hz, _pin: pin })
+}
+
+impl GpoutPin {
+    /// The clock output generator
+    pub fn output(&self) -> Gpout {
+        self.output
+    }
+
+    /// Achieved output frequency in Hz
+    pub fn hz(&self) -> usize {
+        self.hz
+    }
+
+    /// Enables duty cycle correction
+    ///
+    /// Odd integer dividers otherwise give an uneven duty cycle, this evens it out.
+    ///
+    /// `enable`: true for a 50% duty cycle
+    pub fn set_dc50(&self, enable: bool) {
+        generator(self.output.clock()).ctrl().modify(|_, w| w.dc50().bit(enable));
+    }
+}
+
+impl Drop for GpoutPin {
+    /// Stops the generator, the GPIO is released right after
+    fn drop(&mut self) {
+        let _ = clocks::disable(self.output.clock());
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{start, Gpout};
+    use crate::backend::sim;
+    use crate::clocks::{generator, is_enabled, Clock, ClockError, Source};
+    use crate::gpio::Funcsel;
+    use crate::pac::IO_BANK0;
+
+    #[test]
+    fn test_start_routes_source_to_pin() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        // 12 MHz crystal over 2.5
+        let out = start(Gpout::Gpout2, 24, Source::Xosc, 4_800_000).unwrap();
+        assert_eq!(out.hz(), 4_800_000);
+        let div = generator(Clock::Gpout2).div().read();
+        assert_eq!((div.int().bits(), div.frac().bits()), (2, 0x8000));
+        assert_eq!(IO_BANK0.gpio_ctrl(24).read().funcsel().bits(), Funcsel::Gpck as u32);
+
+        out.set_dc50(true);
+        assert!(generator(Clock::Gpout2).ctrl().read().dc50());
+
+        drop(out);
+        assert!(!is_enabled(Clock::Gpout2));
+        assert_eq!(IO_BANK0.gpio_ctrl(24).read().funcsel().bits(), Funcsel::Null as u32);
+    }
+
+    #[test]
+    fn test_start_checks_pin_and_source() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        assert_eq!(start(Gpout::Gpout0, 22, Source::Xosc, 1_000_000).err(), Some(ClockError::InvalidPin));
+        assert_eq!(start(Gpout::Gpout0, 21, Source::Gpin0, 1_000_000).err(), Some(ClockError::UnknownFrequency));
+    }
+}
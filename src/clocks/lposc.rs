@@ -0,0 +1,71 @@
+//! Low power oscillator
+//!
+//! The LPOSC lives in the always-on power domain and runs at a nominal 32.768 kHz for the
+//! lifetime of the chip. It's too slow and imprecise for anything but timekeeping and low-power
+//! phases, its trim moves it by roughly 1% per step.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::clocks::{fc0, Source};
+use crate::pac::POWMAN;
+
+/// Nominal frequency
+pub const NOMINAL_HZ: usize = 32_768;
+
+/// Largest trim value
+pub const TRIM_MAX: u32 = 0x3f;
+
+/// POWMAN registers only accept writes carrying this password in their top half
+const POWMAN_PASSWORD: u32 = 0x5afe << 16;
+
+/// MODE value of a running oscillator
+const MODE_ENABLED: u32 = 0x3;
+
+/// Last measured or estimated frequency
+static LPOSC_FREQ: AtomicUsize = AtomicUsize::new(NOMINAL_HZ);
+
+/// Trims the oscillator, higher values run faster
+///
+/// `trim`: 0 to [`TRIM_MAX`], 32 is the reset value
+pub fn set_trim(trim: u32) {
+    POWMAN.lposc().write_with_zero(|w| {
+        w.bits(POWMAN_PASSWORD).trim().bits(trim.min(TRIM_MAX)).mode().bits(MODE_ENABLED)
+    });
+}
+
+/// The current trim
+pub fn trim() -> u32 {
+    POWMAN.lposc().read().trim().bits()
+}
+
+/// Measures the LPOSC with the frequency counter and remembers it for [`hz`]
+///
+/// clk_ref must run from the XOSC, and the counter only resolves 1/32 kHz.
+pub fn measure() -> usize {
+    let hz = fc0::measure_hz(Source::Lposc);
+    LPOSC_FREQ.store(hz, Ordering::Relaxed);
+    hz
+}
+
+/// Last frequency from [`measure`], or [`NOMINAL_HZ`] if it was never measured
+#[inline(always)]
+pub fn hz() -> usize {
+    LPOSC_FREQ.load(Ordering::Relaxed)
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{set_trim, trim, TRIM_MAX};
+    use crate::backend::sim;
+    use crate::pac::POWMAN;
+
+    #[test]
+    fn test_trim_writes_password() {
+        sim::reset();
+        set_trim(40);
+        assert_eq!(sim::peek(POWMAN.lposc().addr()), 0x5afe_0283);
+        assert_eq!(trim(), 40);
+        set_trim(100);
+        assert_eq!(trim(), TRIM_MAX);
+    }
+}
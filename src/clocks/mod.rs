@@ -1,24 +1,307 @@
 /// Clocks module
-mod regs;
+use core::fmt;
 use core::sync::atomic::{AtomicUsize, Ordering};
-use crate::clocks::regs::*;
-use crate::{bit, reg_read, reg_write, ATOMIC_CLEAR, ATOMIC_SET, RESETS_RESET, RESETS_RESET_DONE};
+use crate::pac::clocks::{
+    ClkAdcCtrlAuxsrc, ClkGpoutCtrlAuxsrc, ClkHstxCtrlAuxsrc, ClkPeriCtrlAuxsrc, ClkRefCtrlAuxsrc,
+    ClkRefCtrlSrc, ClkSysCtrlAuxsrc, ClkSysCtrlSrc, ClkUsbCtrlAuxsrc,
+};
+use crate::pac::xosc::CtrlFreqRange;
+use crate::pac::{CLOCKS, XOSC};
+use crate::{register, register_block};
 
+pub mod fc0;
+pub mod gpout;
+pub mod lposc;
+pub mod pll;
+pub mod resus;
+pub mod rosc;
+pub mod xosc;
+
+use pll::{Pll, PllConfig, PllSolution};
+
+/// Default crystal frequency, see [`xosc::hz`] for the configured one
 pub const XOSC_HZ: usize = 12_000_000;
+/// Default time the crystal gets to settle, in milliseconds
+pub const XOSC_STARTUP_MS: u32 = 10;
+pub const CLK_COUNT: usize = 10;
+
+/// PLL_SYS dividers for the default 150 MHz clk_sys out of a 12 MHz crystal
+pub const PLL_SYS_150MHZ: PllConfig = PllConfig { refdiv: 1, fbdiv: 125, postdiv1: 5, postdiv2: 2 };
+
+/// PLL_USB dividers for the 48 MHz USB and ADC clocks out of a 12 MHz crystal
+pub const PLL_USB_48MHZ: PllConfig = PllConfig { refdiv: 1, fbdiv: 120, postdiv1: 6, postdiv2: 5 };
 
-// TODO: Add all 10 RP clock handles
-/// Match clock handles. only support ref/sys right now.
+/// Clock generators, numbered in register order
 #[repr(usize)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Clock {
-    Ref = 0,
-    Sys = 1,
+    Gpout0 = 0,
+    Gpout1 = 1,
+    Gpout2 = 2,
+    Gpout3 = 3,
+    Ref = 4,
+    Sys = 5,
+    Peri = 6,
+    Hstx = 7,
+    Usb = 8,
+    Adc = 9,
+}
+
+impl Clock {
+    /// Every clock generator, in register order
+    pub const ALL: [Clock; CLK_COUNT] = [
+        Clock::Gpout0, Clock::Gpout1, Clock::Gpout2, Clock::Gpout3, Clock::Ref,
+        Clock::Sys, Clock::Peri, Clock::Hstx, Clock::Usb, Clock::Adc,
+    ];
+
+    /// Name of the generator as in the datasheet
+    pub const fn name(self) -> &'static str {
+        match self {
+            Clock::Gpout0 => "clk_gpout0",
+            Clock::Gpout1 => "clk_gpout1",
+            Clock::Gpout2 => "clk_gpout2",
+            Clock::Gpout3 => "clk_gpout3",
+            Clock::Ref => "clk_ref",
+            Clock::Sys => "clk_sys",
+            Clock::Peri => "clk_peri",
+            Clock::Hstx => "clk_hstx",
+            Clock::Usb => "clk_usb",
+            Clock::Adc => "clk_adc",
+        }
+    }
+
+    /// Whether the generator has a glitchless mux in front of its auxiliary mux
+    #[inline(always)]
+    pub const fn is_glitchless(self) -> bool {
+        matches!(self, Clock::Ref | Clock::Sys)
+    }
+
+    /// Whether the generator can be stopped, clk_ref and clk_sys always run
+    #[inline(always)]
+    pub const fn is_gateable(self) -> bool {
+        !self.is_glitchless()
+    }
+
+    /// Width of the integer part of the divider
+    #[inline(always)]
+    const fn div_int_bits(self) -> u32 {
+        match self {
+            Clock::Gpout0 | Clock::Gpout1 | Clock::Gpout2 | Clock::Gpout3 | Clock::Sys => 16,
+            Clock::Ref => 8,
+            Clock::Usb | Clock::Adc => 4,
+            Clock::Peri | Clock::Hstx => 2,
+        }
+    }
+
+    /// Whether the divider has a fractional part
+    #[inline(always)]
+    const fn has_div_frac(self) -> bool {
+        matches!(self, Clock::Gpout0 | Clock::Gpout1 | Clock::Gpout2 | Clock::Gpout3 | Clock::Sys)
+    }
+}
+
+/// Clock sources, not every generator can select every source
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    PllSys,
+    PllUsb,
+    PllUsbPrimaryRefOpcg,
+    Rosc,
+    RoscPh,
+    Xosc,
+    Lposc,
+    Gpin0,
+    Gpin1,
+    ClkRef,
+    ClkSys,
+    ClkPeri,
+    ClkHstx,
+    ClkUsb,
+    ClkAdc,
+    OtpClk2fc,
+}
+
+impl Source {
+    /// Every clock source
+    pub const ALL: [Source; 16] = [
+        Source::PllSys, Source::PllUsb, Source::PllUsbPrimaryRefOpcg, Source::Rosc,
+        Source::RoscPh, Source::Xosc, Source::Lposc, Source::Gpin0, Source::Gpin1,
+        Source::ClkRef, Source::ClkSys, Source::ClkPeri, Source::ClkHstx, Source::ClkUsb,
+        Source::ClkAdc, Source::OtpClk2fc,
+    ];
+}
+
+/// Errors when configuring a clock generator
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClockError {
+    /// The generator can't select this source
+    InvalidSource,
+    /// The requested frequency is above the source frequency, generators can only divide
+    FrequencyTooHigh,
+    /// The requested frequency is 0 Hz, stop the clock with [`disable`] instead
+    InvalidFrequency,
+    /// The divider needed doesn't fit the generator's divider
+    DividerOutOfRange,
+    /// clk_ref and clk_sys can't be stopped
+    NotGateable,
+    /// No PLL configuration reaches the frequency within the VCO and divider limits
+    PllOutOfRange,
+    /// Every listener slot of the clock is taken
+    TooManyListeners,
+    /// The GPIO can't carry this clock
+    InvalidPin,
+    /// The GPIO is already taken, see [`gpio::is_taken`](crate::gpio::is_taken)
+    PinTaken,
+    /// The driver doesn't know the source's frequency, the GPIN inputs come from outside
+    UnknownFrequency,
+    /// The glitchless mux didn't report the switch in time, the source it leaves or joins may
+    /// be stopped
+    SwitchTimeout,
+}
+
+/// Where a source is selected: on the glitchless mux, or on the auxiliary mux
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Mux {
+    Glitchless(u32),
+    Aux(u32),
+}
+
+/// Mux setting selecting `source` on `clock`
+fn mux(clock: Clock, source: Source) -> Option<Mux> {
+    use Source::*;
+    let mux = match clock {
+        Clock::Gpout0 | Clock::Gpout1 | Clock::Gpout2 | Clock::Gpout3 => Mux::Aux(match source {
+            PllSys => ClkGpoutCtrlAuxsrc::ClksrcPllSys,
+            Gpin0 => ClkGpoutCtrlAuxsrc::ClksrcGpin0,
+            Gpin1 => ClkGpoutCtrlAuxsrc::ClksrcGpin1,
+            PllUsb => ClkGpoutCtrlAuxsrc::ClksrcPllUsb,
+            PllUsbPrimaryRefOpcg => ClkGpoutCtrlAuxsrc::ClksrcPllUsbPrimaryRefOpcg,
+            Rosc => ClkGpoutCtrlAuxsrc::RoscClksrc,
+            Xosc => ClkGpoutCtrlAuxsrc::XoscClksrc,
+            Lposc => ClkGpoutCtrlAuxsrc::LposcClksrc,
+            ClkSys => ClkGpoutCtrlAuxsrc::ClkSys,
+            ClkUsb => ClkGpoutCtrlAuxsrc::ClkUsb,
+            ClkAdc => ClkGpoutCtrlAuxsrc::ClkAdc,
+            ClkRef => ClkGpoutCtrlAuxsrc::ClkRef,
+            ClkPeri => ClkGpoutCtrlAuxsrc::ClkPeri,
+            ClkHstx => ClkGpoutCtrlAuxsrc::ClkHstx,
+            OtpClk2fc => ClkGpoutCtrlAuxsrc::OtpClk2fc,
+            RoscPh => return None,
+        } as u32),
+        Clock::Ref => match source {
+            RoscPh => Mux::Glitchless(ClkRefCtrlSrc::RoscClksrcPh as u32),
+            Xosc => Mux::Glitchless(ClkRefCtrlSrc::XoscClksrc as u32),
+            Lposc => Mux::Glitchless(ClkRefCtrlSrc::LposcClksrc as u32),
+            PllUsb => Mux::Aux(ClkRefCtrlAuxsrc::ClksrcPllUsb as u32),
+            Gpin0 => Mux::Aux(ClkRefCtrlAuxsrc::ClksrcGpin0 as u32),
+            Gpin1 => Mux::Aux(ClkRefCtrlAuxsrc::ClksrcGpin1 as u32),
+            PllUsbPrimaryRefOpcg => Mux::Aux(ClkRefCtrlAuxsrc::ClksrcPllUsbPrimaryRefOpcg as u32),
+            _ => return None,
+        },
+        Clock::Sys => match source {
+            ClkRef => Mux::Glitchless(ClkSysCtrlSrc::ClkRef as u32),
+            PllSys => Mux::Aux(ClkSysCtrlAuxsrc::ClksrcPllSys as u32),
+            PllUsb => Mux::Aux(ClkSysCtrlAuxsrc::ClksrcPllUsb as u32),
+            Rosc => Mux::Aux(ClkSysCtrlAuxsrc::RoscClksrc as u32),
+            Xosc => Mux::Aux(ClkSysCtrlAuxsrc::XoscClksrc as u32),
+            Gpin0 => Mux::Aux(ClkSysCtrlAuxsrc::ClksrcGpin0 as u32),
+            Gpin1 => Mux::Aux(ClkSysCtrlAuxsrc::ClksrcGpin1 as u32),
+            _ => return None,
+        },
+        Clock::Peri => Mux::Aux(match source {
+            ClkSys => ClkPeriCtrlAuxsrc::ClkSys,
+            PllSys => ClkPeriCtrlAuxsrc::ClksrcPllSys,
+            PllUsb => ClkPeriCtrlAuxsrc::ClksrcPllUsb,
+            RoscPh => ClkPeriCtrlAuxsrc::RoscClksrcPh,
+            Xosc => ClkPeriCtrlAuxsrc::XoscClksrc,
+            Gpin0 => ClkPeriCtrlAuxsrc::ClksrcGpin0,
+            Gpin1 => ClkPeriCtrlAuxsrc::ClksrcGpin1,
+            _ => return None,
+        } as u32),
+        Clock::Hstx => Mux::Aux(match source {
+            ClkSys => ClkHstxCtrlAuxsrc::ClkSys,
+            PllSys => ClkHstxCtrlAuxsrc::ClksrcPllSys,
+            PllUsb => ClkHstxCtrlAuxsrc::ClksrcPllUsb,
+            Gpin0 => ClkHstxCtrlAuxsrc::ClksrcGpin0,
+            Gpin1 => ClkHstxCtrlAuxsrc::ClksrcGpin1,
+            _ => return None,
+        } as u32),
+        Clock::Usb => Mux::Aux(match source {
+            PllUsb => ClkUsbCtrlAuxsrc::ClksrcPllUsb,
+            PllSys => ClkUsbCtrlAuxsrc::ClksrcPllSys,
+            RoscPh => ClkUsbCtrlAuxsrc::RoscClksrcPh,
+            Xosc => ClkUsbCtrlAuxsrc::XoscClksrc,
+            Gpin0 => ClkUsbCtrlAuxsrc::ClksrcGpin0,
+            Gpin1 => ClkUsbCtrlAuxsrc::ClksrcGpin1,
+            _ => return None,
+        } as u32),
+        Clock::Adc => Mux::Aux(match source {
+            PllUsb => ClkAdcCtrlAuxsrc::ClksrcPllUsb,
+            PllSys => ClkAdcCtrlAuxsrc::ClksrcPllSys,
+            RoscPh => ClkAdcCtrlAuxsrc::RoscClksrcPh,
+            Xosc => ClkAdcCtrlAuxsrc::XoscClksrc,
+            Gpin0 => ClkAdcCtrlAuxsrc::ClksrcGpin0,
+            Gpin1 => ClkAdcCtrlAuxsrc::ClksrcGpin1,
+            _ => return None,
+        } as u32),
+    };
+    Some(mux)
+}
+
+// Every generator has the same CTRL/DIV/SELECTED triple, this is the union of their layouts.
+// Fields a generator doesn't have are reserved and read as 0.
+register! {
+    /// CLK_x_CTRL
+    pub GeneratorCtrl (reset = 0x0000_0000) {
+        enabled: bit(28),
+        nudge: bit(20),
+        phase: bits(16, 2),
+        dc50: bit(12),
+        enable: bit(11),
+        kill: bit(10),
+        auxsrc: bits(5, 4),
+        src: bits(0, 2),
+    }
+}
+
+register! {
+    /// CLK_x_DIV, 16.16 fixed point divider
+    pub GeneratorDiv (reset = 0x0001_0000) {
+        int: bits(16, 16),
+        frac: bits(0, 16),
+    }
+}
+
+register! {
+    /// CLK_x_SELECTED, one hot
+    pub GeneratorSelected (reset = 0x0000_0001) {
+        selected: bits(0, 4),
+    }
+}
+
+register_block! {
+    /// Registers of one clock generator
+    pub Generator {
+        0x0 => ctrl: GeneratorCtrl,
+        0x4 => div: GeneratorDiv,
+        0x8 => selected: GeneratorSelected,
+    }
 }
 
-static CONFIGURED_FREQ: [AtomicUsize; CLK_COUNT] = [
-    AtomicUsize::new(0),
-    AtomicUsize::new(0),
-];
+/// Stride between two generators' registers
+const GENERATOR_STRIDE: usize = 0xc;
+
+/// Registers of a clock generator, whichever it is
+#[inline(always)]
+pub const fn generator(clock: Clock) -> Generator {
+    Generator::at(CLOCKS.base() + clock as usize * GENERATOR_STRIDE)
+}
+
+static CONFIGURED_FREQ: [AtomicUsize; CLK_COUNT] = [const { AtomicUsize::new(0) }; CLK_COUNT];
+
+/// Frequency of a generator while it's stopped, restored by [`enable`]
+static STOPPED_FREQ: [AtomicUsize; CLK_COUNT] = [const { AtomicUsize::new(0) }; CLK_COUNT];
+
 /// Get the cached clock frequency of a specific clock handle in Hz
 #[inline(always)]
 pub fn clock_get_hz(clock: Clock) -> usize {
@@ -26,74 +309,532 @@ pub fn clock_get_hz(clock: Clock) -> usize {
 }
 
 /// Set the clock frequency of a specific clock handle in Hz
+///
+/// The clock's listeners are notified if the frequency changed, see [`add_listener`]
 #[inline(always)]
 pub fn clock_set_reported_hz(clock: Clock, hz: usize) {
-    CONFIGURED_FREQ[clock as usize].store(hz, Ordering::Relaxed)
+    if CONFIGURED_FREQ[clock as usize].swap(hz, Ordering::Relaxed) != hz {
+        notify(clock, hz);
+    }
 }
 
-/// Measure the PLL system clock in Hz
+/// Called with the clock and its new frequency in Hz (0 once stopped) after it changed
+pub type ClockListener = fn(clock: Clock, hz: usize);
+
+/// Listener slots per clock
+pub const LISTENERS_PER_CLOCK: usize = 4;
+
+/// Registered listeners as function addresses, 0 marks a free slot
+static LISTENERS: [[AtomicUsize; LISTENERS_PER_CLOCK]; CLK_COUNT] =
+    [const { [const { AtomicUsize::new(0) }; LISTENERS_PER_CLOCK] }; CLK_COUNT];
+
+/// Registers a driver callback run whenever a clock's frequency changes
+///
+/// Listeners run in the context of whoever changed the clock, before the change returns.
+/// Registering the same listener twice is a no-op.
+///
+/// `clock`: the clock to watch
+/// `listener`: the callback
+pub fn add_listener(clock: Clock, listener: ClockListener) -> Result<(), ClockError> {
+    let raw = listener as usize;
+    let slots = &LISTENERS[clock as usize];
+    if slots.iter().any(|slot| slot.load(Ordering::Acquire) == raw) {
+        return Ok(());
+    }
+    for slot in slots {
+        if slot.compare_exchange(0, raw, Ordering::AcqRel, Ordering::Acquire).is_ok() {
+            return Ok(());
+        }
+    }
+    Err(ClockError::TooManyListeners)
+}
+
+/// Unregisters a listener added with [`add_listener`]
+///
+/// `clock`: the watched clock
+/// `listener`: the callback to remove
+pub fn remove_listener(clock: Clock, listener: ClockListener) {
+    let raw = listener as usize;
+    for slot in &LISTENERS[clock as usize] {
+        let _ = slot.compare_exchange(raw, 0, Ordering::AcqRel, Ordering::Acquire);
+    }
+}
+
+/// Runs every listener of a clock
+fn notify(clock: Clock, hz: usize) {
+    for slot in &LISTENERS[clock as usize] {
+        let raw = slot.load(Ordering::Acquire);
+        if raw != 0 {
+            // SAFETY: non-zero slots only ever hold a `ClockListener` stored by `add_listener`
+            let listener = unsafe { core::mem::transmute::<usize, ClockListener>(raw) };
+            listener(clock, hz);
+        }
+    }
+}
+
+/// Source a generator currently selects, read back from its muxes
+///
+/// `clock`: the generator to read
+pub fn source(clock: Clock) -> Option<Source> {
+    let ctrl = generator(clock).ctrl().read();
+    let current = match ctrl.src().bits() {
+        src if clock.is_glitchless() && src != 1 => Mux::Glitchless(src),
+        _ => Mux::Aux(ctrl.auxsrc().bits()),
+    };
+    Source::ALL.into_iter().find(|&source| mux(clock, source) == Some(current))
+}
+
+/// Current frequency of a clock source in Hz, `None` for the external GPIN inputs
+///
+/// Oscillators report their configured or last measured frequency, PLLs their programmed
+/// output and generator outputs their cached frequency.
+///
+/// `source`: the source to look up
+pub fn source_hz(source: Source) -> Option<usize> {
+    let hz = match source {
+        Source::PllSys => pll_sys_out_hz(),
+        Source::PllUsb | Source::PllUsbPrimaryRefOpcg => pll_usb_out_hz(),
+        Source::Rosc | Source::RoscPh => rosc::hz(),
+        Source::Xosc => xosc::hz(),
+        Source::Lposc => lposc::hz(),
+        Source::ClkRef => clock_get_hz(Clock::Ref),
+        Source::ClkSys => clock_get_hz(Clock::Sys),
+        Source::ClkPeri => clock_get_hz(Clock::Peri),
+        Source::ClkHstx => clock_get_hz(Clock::Hstx),
+        Source::ClkUsb => clock_get_hz(Clock::Usb),
+        Source::ClkAdc => clock_get_hz(Clock::Adc),
+        Source::Gpin0 | Source::Gpin1 | Source::OtpClk2fc => return None,
+    };
+    Some(hz)
+}
+
+/// Generator whose output a source is, `None` for oscillators, PLLs and external inputs
+///
+/// `source`: the source to look up
+const fn generator_of(source: Source) -> Option<Clock> {
+    match source {
+        Source::ClkRef => Some(Clock::Ref),
+        Source::ClkSys => Some(Clock::Sys),
+        Source::ClkPeri => Some(Clock::Peri),
+        Source::ClkHstx => Some(Clock::Hstx),
+        Source::ClkUsb => Some(Clock::Usb),
+        Source::ClkAdc => Some(Clock::Adc),
+        _ => None,
+    }
+}
+
+/// 16.16 fixed point divider bringing `src_hz` down to `freq_hz` on `clock`
+///
+/// Generators without a fractional divider round to the nearest integer divider
+fn divider(clock: Clock, src_hz: usize, freq_hz: usize) -> Result<u32, ClockError> {
+    if freq_hz == 0 {
+        return Err(ClockError::InvalidFrequency);
+    }
+    if freq_hz > src_hz {
+        return Err(ClockError::FrequencyTooHigh);
+    }
+    let mut div = ((src_hz as u64) << 16) / freq_hz as u64;
+    if !clock.has_div_frac() {
+        div = (div + 0x8000) & !0xffff;
+    }
+    // The integer part has to fit its field, 0 there would stand for the maximum + 1
+    if div >= 1 << (16 + clock.div_int_bits()) {
+        return Err(ClockError::DividerOutOfRange);
+    }
+    Ok(div as u32)
+}
+
+/// 16.16 fixed point divider a generator is programmed with
+///
+/// An integer part of 0 stands for the largest integer the field holds + 1.
+///
+/// `clock`: the generator to read
+fn divider_of(clock: Clock) -> u64 {
+    let div = generator(clock).div().read().bits() as u64;
+    if div >> 16 == 0 {
+        div + (1 << (16 + clock.div_int_bits()))
+    } else {
+        div
+    }
+}
+
+/// Polls a glitchless generator's SELECTED register until `done` accepts it, at most `polls` times
+///
+/// `clock`: the generator to poll
+/// `done`: whether the one-hot SELECTED value shows the expected source
+/// `polls`: reads before giving up with [`ClockError::SwitchTimeout`]
+fn wait_selected(clock: Clock, done: impl Fn(u32) -> bool, polls: usize) -> Result<(), ClockError> {
+    for _ in 0..polls {
+        if done(generator(clock).selected().read().selected().bits()) {
+            return Ok(());
+        }
+    }
+    Err(ClockError::SwitchTimeout)
+}
+
+/// Spins for at least `cycles` clk_sys cycles
 #[inline(always)]
-pub fn pll_sys_out_hz() -> usize {
-    // postdiv1 bits [18:16], postdiv2 bits [14:12]
-    let fbdiv = reg_read(PLL_SYS_FBDIV_INT) & 0x0fff;
+fn delay_cycles(cycles: usize) {
+    for _ in 0..cycles {
+        core::hint::spin_loop();
+    }
+}
+
+/// Routes a source to a clock generator and divides it down to the requested frequency
+///
+/// Follows the datasheet's switching sequence: glitchless generators are parked on their
+/// glitchless source while the auxiliary mux changes, the others are stopped for a few cycles.
+/// Returns the achieved frequency, which is also cached for [`clock_get_hz`].
+///
+/// `clock`: the generator to configure
+/// `source`: the source to select
+/// `src_hz`: frequency of the source in Hz
+/// `freq_hz`: requested output frequency in Hz
+pub fn configure(clock: Clock, source: Source, src_hz: usize, freq_hz: usize) -> Result<usize, ClockError> {
+    configure_polling(clock, source, src_hz, freq_hz, usize::MAX)
+}
+
+/// [`configure`], giving up on a glitchless mux that hasn't switched after `polls` reads
+///
+/// The glitchless mux needs a running clock on both sides, an interrupt handler moving a
+/// generator off a dead source can't afford to wait forever. On [`ClockError::SwitchTimeout`]
+/// the generator may still select its old source, its cached frequency is left untouched.
+///
+/// `clock`: the generator to configure
+/// `source`: the source to select
+/// `src_hz`: frequency of the source in Hz
+/// `freq_hz`: requested output frequency in Hz
+/// `polls`: reads of the SELECTED register before giving up
+pub(crate) fn configure_polling(
+    clock: Clock,
+    source: Source,
+    src_hz: usize,
+    freq_hz: usize,
+    polls: usize,
+) -> Result<usize, ClockError> {
+    let mux = mux(clock, source).ok_or(ClockError::InvalidSource)?;
+    let div = divider(clock, src_hz, freq_hz)?;
+    let gen = generator(clock);
+
+    // Raise the divider first if it grows, so the output never overshoots
+    if div as u64 > divider_of(clock) {
+        gen.div().write(|w| w.bits(div));
+    }
+
+    if clock.is_glitchless() {
+        // Park on the glitchless source 0 before touching the auxiliary mux
+        if let Mux::Aux(_) = mux {
+            gen.ctrl().clear_bits(|w| w.src().bits(0b11));
+            wait_selected(clock, |selected| selected & 1 != 0, polls)?;
+        }
+    } else {
+        // Stop the generator and let the enable propagate (3 cycles of the old output)
+        gen.ctrl().clear_bits(|w| w.enable().set_bit());
+        let old_hz = clock_get_hz(clock);
+        if old_hz > 0 {
+            let sys_hz = clock_get_hz(Clock::Sys).max(old_hz);
+            delay_cycles(3 * (sys_hz / old_hz + 1));
+        }
+    }
+
+    match mux {
+        Mux::Aux(auxsrc) => {
+            gen.ctrl().modify(|_, w| w.auxsrc().bits(auxsrc));
+            if clock.is_glitchless() {
+                gen.ctrl().modify(|_, w| w.src().bits(1));
+                wait_selected(clock, |selected| selected == 1 << 1, polls)?;
+            }
+        }
+        Mux::Glitchless(src) => {
+            gen.ctrl().modify(|_, w| w.src().bits(src));
+            wait_selected(clock, |selected| selected == 1 << src, polls)?;
+        }
+    }
+
+    if clock.is_gateable() {
+        gen.ctrl().set_bits(|w| w.enable().set_bit());
+    }
+    gen.div().write(|w| w.bits(div));
+
+    let hz = (((src_hz as u64) << 16) / div as u64) as usize;
+    clock_set_reported_hz(clock, hz);
+    STOPPED_FREQ[clock as usize].store(hz, Ordering::Relaxed);
+    Ok(hz)
+}
+
+/// Stops a clock generator cleanly, its cached frequency reads 0 until [`enable`]
+///
+/// `clock`: the generator to stop
+pub fn disable(clock: Clock) -> Result<(), ClockError> {
+    if !clock.is_gateable() {
+        return Err(ClockError::NotGateable);
+    }
+    generator(clock).ctrl().clear_bits(|w| w.enable().set_bit());
+    clock_set_reported_hz(clock, 0);
+    Ok(())
+}
+
+/// Restarts a clock generator stopped by [`disable`] with its previous configuration
+///
+/// `clock`: the generator to start
+pub fn enable(clock: Clock) -> Result<(), ClockError> {
+    if !clock.is_gateable() {
+        return Err(ClockError::NotGateable);
+    }
+    generator(clock).ctrl().set_bits(|w| w.enable().set_bit());
+    clock_set_reported_hz(clock, STOPPED_FREQ[clock as usize].load(Ordering::Relaxed));
+    Ok(())
+}
+
+/// Whether a clock generator is running
+///
+/// `clock`: the generator to check
+pub fn is_enabled(clock: Clock) -> bool {
+    !clock.is_gateable() || generator(clock).ctrl().read().enabled()
+}
+
+/// Largest difference between a measured and a cached frequency that [`verify`] accepts
+pub const VERIFY_TOLERANCE_HZ: usize = 2_000;
+
+/// A generator whose measured frequency doesn't match its cached one
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub clock: Clock,
+    /// Frequency cached by [`clock_get_hz`]
+    pub configured_hz: usize,
+    /// Frequency counted by FC0
+    pub measured_hz: usize,
+}
 
-    let prim = reg_read(PLL_SYS_PRIM);
-    let post_div1 = (prim >> 16) & 0x7;
-    let post_div2 = (prim >> 12) & 0x7;
+/// Measures every generator FC0 can count and yields those off their cached frequency
+///
+/// Each generator is measured when the iterator reaches it, about 1 ms per generator.
+pub fn verify() -> impl Iterator<Item = Mismatch> {
+    Clock::ALL.into_iter().filter_map(|clock| {
+        let source = fc0::clock_source(clock)?;
+        let configured_hz = clock_get_hz(clock);
+        let measured_hz = fc0::measure_hz(source);
+        (configured_hz.abs_diff(measured_hz) > VERIFY_TOLERANCE_HZ).then_some(Mismatch {
+            clock,
+            configured_hz,
+            measured_hz,
+        })
+    })
+}
+
+/// Writes the whole clock tree in a human readable form, for diagnostics
+///
+/// One line per generator with its source, divider, state, cached and measured frequency,
+/// then both PLLs and the XOSC. Generators are measured with FC0, about 1 ms each.
+///
+/// ```ignore
+/// clocks::dump(&mut UartWriter)?;
+/// ```
+///
+/// `out`: where to write, [`UartWriter`](crate::UartWriter) to print it
+pub fn dump(out: &mut impl fmt::Write) -> fmt::Result {
+    for clock in Clock::ALL {
+        let div = divider_of(clock);
+        write!(out, "{:<11}", clock.name())?;
+        match source(clock) {
+            Some(source) => write!(out, "src {:?}", source)?,
+            None => write!(out, "src ?")?,
+        }
+        // 16.16 divider, the fraction shown to 4 decimals
+        let frac = ((div & 0xffff) * 10_000) >> 16;
+        write!(out, " div {}.{:04}", div >> 16, frac)?;
+        write!(out, " {}", if is_enabled(clock) { "enabled" } else { "stopped" })?;
+        write!(out, " cached {} Hz", clock_get_hz(clock))?;
+        match fc0::clock_source(clock) {
+            Some(source) => writeln!(out, " measured {} Hz", fc0::measure_hz(source))?,
+            None => writeln!(out)?,
+        }
+    }
+
+    for pll in [Pll::Sys, Pll::Usb] {
+        let regs = pll.regs();
+        let cs = regs.cs().read();
+        let pwr = regs.pwr().read();
+        let prim = regs.prim().read();
+        write!(
+            out,
+            "{:<11}refdiv {} fbdiv {} postdiv {}/{}",
+            if pll == Pll::Sys { "pll_sys" } else { "pll_usb" },
+            cs.refdiv().bits(),
+            regs.fbdiv_int().read().fbdiv_int().bits(),
+            prim.postdiv1().bits(),
+            prim.postdiv2().bits(),
+        )?;
+        if pwr.pd() || pwr.vcopd() {
+            writeln!(out, " powered down")?;
+        } else {
+            let lock = if cs.lock() { "locked" } else { "unlocked" };
+            writeln!(out, " out {} Hz {}", pll::out_hz(pll, xosc::hz()), lock)?;
+        }
+    }
+
+    let ctrl = XOSC.ctrl().read();
+    let status = XOSC.status().read();
+    write!(out, "{:<11}{} Hz", "xosc", xosc::hz())?;
+    match ctrl.freq_range().variant() {
+        Some(range) => write!(out, " range {:?}", range)?,
+        None => write!(out, " range ?")?,
+    }
+    writeln!(
+        out,
+        " {} {}",
+        if status.enabled() { "enabled" } else { "disabled" },
+        if status.stable() { "stable" } else { "unstable" },
+    )
+}
 
-    // TODO: add support REFDIV != 1, read it from PLL_SYS_CS.
-    let refdiv = 1usize;
+/// Measure the PLL system clock in Hz
+#[inline(always)]
+pub fn pll_sys_out_hz() -> usize {
+    pll::out_hz(Pll::Sys, xosc::hz())
+}
 
-    let vco = (XOSC_HZ / refdiv) * fbdiv;
-    vco / (post_div1 * post_div2)
+/// Measure the PLL USB clock in Hz
+#[inline(always)]
+pub fn pll_usb_out_hz() -> usize {
+    pll::out_hz(Pll::Usb, xosc::hz())
 }
 
 /// Select XOSC as the reference clock
 ///
 /// # Safety
 ///
-/// The caller must ensure the reference clock isn't already initialized
+/// The caller must ensure the XOSC is running
 pub unsafe fn configure_clk_ref() {
-    // Select XOSC as the reference source
-    reg_write(
-        CLOCKS_CLK_REF_CTRL,
-        (reg_read(CLOCKS_CLK_REF_CTRL) & !0x3) | 0x2,
-    );
-
-    // Wait until XOSC is selected
-    while (reg_read(CLOCKS_CLK_REF_SELECTED) & 0x0f) != 0x04 {}
-
-    clock_set_reported_hz(Clock::Ref, XOSC_HZ);
+    let xosc_hz = xosc::hz();
+    let _ = configure(Clock::Ref, Source::Xosc, xosc_hz, xosc_hz);
 }
 
 /// Select PLL as the system clock
 ///
 /// # Safety
 ///
-/// The caller must ensure the system clock isn't already initialized
+/// The caller must ensure the PLL is locked
 pub unsafe fn configure_clk_sys() {
-    // Select AUX (PLL_SYS) as the system clock source
-    reg_write(CLOCKS_CLK_SYS_CTRL + ATOMIC_SET, 1);
+    let sys_hz = pll_sys_out_hz();
+    let _ = configure(Clock::Sys, Source::PllSys, sys_hz, sys_hz);
+}
 
-    // Wait until AUX is selected
-    while (reg_read(CLOCKS_CLK_SYS_SELECTED) & 0x03) != 0x02 {}
+/// Run the peripheral clock from the XOSC, undivided
+///
+/// # Safety
+///
+/// The caller must ensure the XOSC is running and no peripheral is using clk_peri
+pub unsafe fn configure_clk_peri() {
+    let xosc_hz = xosc::hz();
+    let _ = configure(Clock::Peri, Source::Xosc, xosc_hz, xosc_hz);
+}
 
-    let sys_hz = pll_sys_out_hz();
-    clock_set_reported_hz(Clock::Sys, sys_hz);
+/// Run the USB clock from PLL_USB at 48 MHz
+///
+/// # Safety
+///
+/// The caller must ensure PLL_USB is locked and the USB controller isn't running
+pub unsafe fn configure_clk_usb() {
+    let usb_hz = pll_usb_out_hz();
+    let _ = configure(Clock::Usb, Source::PllUsb, usb_hz, 48_000_000);
+}
+
+/// Run the ADC clock from PLL_USB at 48 MHz
+///
+/// # Safety
+///
+/// The caller must ensure PLL_USB is locked and the ADC isn't converting
+pub unsafe fn configure_clk_adc() {
+    let usb_hz = pll_usb_out_hz();
+    let _ = configure(Clock::Adc, Source::PllUsb, usb_hz, 48_000_000);
+}
+
+/// Runs clk_sys from the ROSC and powers PLL_SYS down
+///
+/// clk_sys is parked on clk_ref while its auxiliary mux moves, so it never glitches.
+/// Returns the new clk_sys frequency, the ROSC's last measured or nominal frequency.
+///
+/// # Safety
+///
+/// The caller must ensure nothing else runs from PLL_SYS and everything clocked by clk_sys
+/// copes with the slower clock
+pub unsafe fn switch_sys_to_rosc() -> Result<usize, ClockError> {
+    rosc::enable();
+    let rosc_hz = rosc::hz();
+    let hz = configure(Clock::Sys, Source::Rosc, rosc_hz, rosc_hz)?;
+    pll::power_down(Pll::Sys);
+    Ok(hz)
+}
+
+/// Runs clk_sys from PLL_SYS at the frequency closest to `target_hz`
+///
+/// Works from any clk_sys source, including PLL_SYS itself: clk_sys is parked on clk_ref while
+/// PLL_SYS is reprogrammed and locks. The ROSC keeps running, see [`rosc::disable`].
+///
+/// # Safety
+///
+/// The caller must ensure the XOSC runs, nothing else runs from PLL_SYS and everything clocked
+/// by clk_sys copes with the new clock
+///
+/// `target_hz`: requested clk_sys frequency in Hz
+pub unsafe fn switch_sys_to_pll(target_hz: usize) -> Result<PllSolution, ClockError> {
+    let solution = pll::solve(xosc::hz(), target_hz)?;
+
+    let ref_hz = clock_get_hz(Clock::Ref);
+    configure(Clock::Sys, Source::ClkRef, ref_hz, ref_hz)?;
+    pll::init(Pll::Sys, &solution.config);
+    configure(Clock::Sys, Source::PllSys, solution.hz, solution.hz)?;
+    Ok(solution)
+}
+
+/// Changes clk_sys at runtime and lets the drivers know
+///
+/// Reprograms PLL_SYS through [`switch_sys_to_pll`], then updates the cached frequency of every
+/// running generator fed by clk_sys or straight by PLL_SYS, directly or through another generator,
+/// keeping its divider. Listeners of all of them are notified, clk_sys' own may also see the
+/// clk_ref frequency it's parked on in between.
+///
+/// # Safety
+///
+/// Same as [`switch_sys_to_pll`]
+///
+/// `hz`: requested clk_sys frequency in Hz
+pub unsafe fn set_sys_hz(hz: usize) -> Result<PllSolution, ClockError> {
+    let solution = switch_sys_to_pll(hz)?;
+
+    // clk_sys itself was configured by the switch. Generators can feed each other (a GPOUT from
+    // clk_peri from clk_sys), so pass again until no generator picks up an updated input.
+    let mut updated = 1u32 << Clock::Sys as usize;
+    loop {
+        let before = updated;
+        for clock in Clock::ALL {
+            let bit = 1u32 << clock as usize;
+            let Some(source) = source(clock) else { continue };
+            let fed = source == Source::PllSys
+                || generator_of(source).is_some_and(|input| updated & 1 << input as usize != 0);
+            if updated & bit != 0 || !fed || !is_enabled(clock) {
+                continue;
+            }
+            let src_hz = source_hz(source).unwrap_or(0);
+            let hz = (((src_hz as u64) << 16) / divider_of(clock)) as usize;
+            clock_set_reported_hz(clock, hz);
+            STOPPED_FREQ[clock as usize].store(hz, Ordering::Relaxed);
+            updated |= bit;
+        }
+        if updated == before {
+            return Ok(solution);
+        }
+    }
 }
 
-/// Initializes the external crystal oscillator (XOSC)
+/// Initializes the external crystal oscillator (XOSC) for the default 12 MHz crystal
 ///
 /// # Safety
 ///
 /// The caller must ensure the XOSC isn't already initialized
 pub unsafe fn init_xosc() {
-    reg_write(XOSC_STARTUP, (reg_read(XOSC_STARTUP) & !0x3fff) | 469);
-    reg_write(XOSC_CTRL, (reg_read(XOSC_CTRL) & !0x00ffffff) | 0xfabaa0);
-
-    while (reg_read(XOSC_STATUS) & (1 << 31)) == 0 {}
-    // XOSC is now running
-    clock_set_reported_hz(Clock::Ref, XOSC_HZ); // optional here, but convenient
+    xosc::init(XOSC_HZ, CtrlFreqRange::_1_15mhz, xosc::startup_delay(XOSC_HZ, XOSC_STARTUP_MS));
 }
 
 /// Initializes the Phase Locked Loop (PLL)
@@ -102,32 +843,26 @@ pub unsafe fn init_xosc() {
 ///
 /// The caller must ensure the PLL isn't already initialized
 pub unsafe fn init_pll() {
-    reg_write(RESETS_RESET + ATOMIC_CLEAR, bit(14));
-    while reg_read(RESETS_RESET_DONE) & bit(14) != bit(14) {}
-
-    reg_write(
-        PLL_SYS_FBDIV_INT,
-        (reg_read(PLL_SYS_FBDIV_INT) & !0xfff) | 125,
-    );
-    reg_write(PLL_SYS_PWR + ATOMIC_CLEAR, bit(5) | bit(0));
-
-    while reg_read(PLL_SYS_CS) & (1 << 31) != 0 {}
-
-    reg_write(
-        PLL_SYS_PRIM,
-        (reg_read(PLL_SYS_PRIM) & !0x77000) | ((5 << 16) | (2 << 12)),
-    );
-    reg_write(PLL_SYS_PWR + ATOMIC_CLEAR, bit(3));
-    // Now PLL output frequency is defined by XOSC, FBDIV, POSTDIVs
+    pll::init(Pll::Sys, &PLL_SYS_150MHZ);
+    // Now PLL output frequency is defined by XOSC, REFDIV, FBDIV, POSTDIVs
     let pll_sys_hz = pll_sys_out_hz();
     clock_set_reported_hz(Clock::Sys, pll_sys_hz);
 }
 
-#[cfg(test)]
+/// Initializes the USB Phase Locked Loop (PLL_USB) at 48 MHz
+///
+/// # Safety
+///
+/// The caller must ensure the XOSC is running and neither clk_usb nor clk_adc runs from PLL_USB
+pub unsafe fn init_pll_usb() {
+    pll::init(Pll::Usb, &PLL_USB_48MHZ);
+}
+
+#[cfg(all(test, target_os = "none"))]
 mod tests {
-    use crate::clocks::{clock_get_hz, pll_sys_out_hz, XOSC_HZ};
-    use crate::clocks::Clock::{Ref, Sys};
-    use crate::println;
+    use crate::clocks::{clock_get_hz, dump, pll_sys_out_hz, pll_usb_out_hz, verify, XOSC_HZ};
+    use crate::clocks::Clock::{Ref, Sys, Usb};
+    use crate::{println, UartWriter};
 
     #[test_case]
     fn test_reported_clock_ref_freq() {
@@ -144,4 +879,325 @@ mod tests {
         println!("Measured Frequency: {}\nReported Frequency: {}", measured_freq, reported_freq);
         assert_eq!(reported_freq, measured_freq);
     }
+
+    #[test_case]
+    fn test_reported_clock_usb_freq() {
+        let measured_freq = pll_usb_out_hz();
+        let reported_freq = clock_get_hz(Usb);
+        println!("Measured Frequency: {}\nReported Frequency: {}", measured_freq, reported_freq);
+        assert_eq!(reported_freq, 48_000_000);
+        assert_eq!(reported_freq, measured_freq);
+    }
+
+    #[test_case]
+    fn test_measured_clocks_match_reported() {
+        for mismatch in verify() {
+            println!("{:?}", mismatch);
+        }
+        assert_eq!(verify().count(), 0);
+    }
+
+    #[test_case]
+    fn test_dump_clock_tree() {
+        assert!(dump(&mut UartWriter).is_ok());
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{
+        add_listener, clock_get_hz, clock_set_reported_hz, configure, configure_clk_adc,
+        configure_clk_ref, configure_clk_sys, configure_clk_usb, disable, dump, enable, generator,
+        init_pll, init_pll_usb, init_xosc, is_enabled, pll_sys_out_hz, pll_usb_out_hz,
+        remove_listener, set_sys_hz, switch_sys_to_pll, verify, Clock, ClockError, ClockListener, Source, LISTENERS_PER_CLOCK,
+        XOSC_HZ,
+    };
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use crate::pac::clocks::Fc0SrcFc0Src;
+    use crate::backend::sim;
+    use crate::pac::{CLOCKS, PLL_SYS, PLL_USB, XOSC};
+    use crate::resets::{is_in_reset, Reset};
+
+    #[test]
+    fn test_pll_sys_out_hz_from_dividers() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        PLL_SYS.fbdiv_int().write(|w| w.fbdiv_int().bits(125));
+        PLL_SYS.prim().write(|w| w.postdiv1().bits(5).postdiv2().bits(2));
+        assert_eq!(pll_sys_out_hz(), 150_000_000);
+        PLL_SYS.cs().write(|w| w.refdiv().bits(2));
+        assert_eq!(pll_sys_out_hz(), 75_000_000);
+    }
+
+    #[test]
+    fn test_init_xosc_waits_for_stable() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        XOSC.status().write(|w| w.stable().set_bit());
+        unsafe { init_xosc() };
+        assert_eq!(XOSC.startup().read().delay().bits(), 469);
+        assert_eq!(sim::peek(XOSC.ctrl().addr()), 0xfabaa0);
+        assert_eq!(clock_get_hz(Clock::Ref), XOSC_HZ);
+    }
+
+    #[test]
+    fn test_init_pll_programs_150mhz() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        unsafe { init_pll() };
+        assert!(!is_in_reset(Reset::PllSys));
+        assert_eq!(PLL_SYS.fbdiv_int().read().fbdiv_int().bits(), 125);
+        assert_eq!(sim::peek(PLL_SYS.pwr().addr()), 0x04);
+        assert_eq!(pll_sys_out_hz(), 150_000_000);
+    }
+
+    #[test]
+    fn test_usb_and_adc_run_from_pll_usb() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        unsafe { init_pll_usb() };
+        assert!(!is_in_reset(Reset::PllUsb));
+        assert!(PLL_USB.cs().read().lock());
+        assert_eq!(pll_usb_out_hz(), 48_000_000);
+
+        unsafe {
+            configure_clk_usb();
+            configure_clk_adc();
+        }
+        assert_eq!(clock_get_hz(Clock::Usb), 48_000_000);
+        assert_eq!(clock_get_hz(Clock::Adc), 48_000_000);
+        assert_eq!(CLOCKS.clk_usb_ctrl().read().auxsrc().bits(), 0);
+        assert!(is_enabled(Clock::Adc));
+    }
+
+    #[test]
+    fn test_dump_describes_clock_tree() {
+        extern crate std;
+        use std::string::String;
+
+        let _clocks = sim::exclusive();
+        sim::reset();
+        XOSC.status().write(|w| w.enabled().set_bit().stable().set_bit());
+        unsafe {
+            init_xosc();
+            configure_clk_ref();
+            init_pll();
+            configure_clk_sys();
+        }
+
+        // An integer part of 0 is the largest divider
+        sim::poke(generator(Clock::Hstx).div().addr(), 0);
+
+        let mut out = String::new();
+        dump(&mut out).unwrap();
+        let lines: std::vec::Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 13);
+        assert!(lines[7].starts_with("clk_hstx") && lines[7].contains(" div 4.0000 "));
+        assert_eq!(lines[4], "clk_ref    src Xosc div 1.0000 enabled cached 12000000 Hz measured 0 Hz");
+        assert!(lines[5].starts_with("clk_sys    src PllSys div 1.0000 enabled cached 150000000 Hz"));
+        assert!(lines[0].starts_with("clk_gpout0 src PllSys") && lines[0].contains("stopped"));
+        assert_eq!(lines[10], "pll_sys    refdiv 1 fbdiv 125 postdiv 5/2 out 150000000 Hz locked");
+        assert!(lines[11].ends_with("powered down"));
+        assert_eq!(lines[12], "xosc       12000000 Hz range _1_15mhz enabled stable");
+    }
+
+    #[test]
+    fn test_verify_reports_mismatches() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        // FC0 measures every clock at its cached frequency but clk_hstx, which runs at 7 kHz
+        sim::on_read(CLOCKS.fc0_result().addr(), |_, _| {
+            let clock = match CLOCKS.fc0_src().read().fc0_src().variant() {
+                Some(Fc0SrcFc0Src::ClkHstx) => return 7 << 5,
+                Some(Fc0SrcFc0Src::ClkRef) => Clock::Ref,
+                Some(Fc0SrcFc0Src::ClkSys) => Clock::Sys,
+                Some(Fc0SrcFc0Src::ClkPeri) => Clock::Peri,
+                Some(Fc0SrcFc0Src::ClkUsb) => Clock::Usb,
+                Some(Fc0SrcFc0Src::ClkAdc) => Clock::Adc,
+                _ => unreachable!(),
+            };
+            ((clock_get_hz(clock) / 1000) << 5) as u32
+        });
+        let mut mismatches = verify();
+        let hstx = mismatches.next().unwrap();
+        assert_eq!(hstx.clock, Clock::Hstx);
+        assert_eq!(hstx.measured_hz, 7_000);
+        assert_eq!(mismatches.next(), None);
+    }
+
+    #[test]
+    fn test_switch_sys_to_pll_parks_on_clk_ref() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        clock_set_reported_hz(Clock::Ref, XOSC_HZ);
+        // Running from the ROSC
+        CLOCKS.clk_sys_ctrl().write(|w| w.auxsrc().bits(2).src().bits(1));
+        let solution = unsafe { switch_sys_to_pll(150_000_000) }.unwrap();
+        assert_eq!(solution.hz, 150_000_000);
+        assert_eq!(CLOCKS.clk_sys_ctrl().read().auxsrc().bits(), 0);
+        assert_eq!(CLOCKS.clk_sys_selected().read().bits(), 1 << 1);
+        assert!(PLL_SYS.cs().read().lock());
+        assert_eq!(clock_get_hz(Clock::Sys), 150_000_000);
+    }
+
+    #[test]
+    fn test_listeners_follow_clock_changes() {
+        static SEEN: AtomicUsize = AtomicUsize::new(0);
+        fn listener(clock: Clock, hz: usize) {
+            assert_eq!(clock, Clock::Gpout2);
+            SEEN.store(hz, Ordering::Relaxed);
+        }
+        let _clocks = sim::exclusive();
+        sim::reset();
+        add_listener(Clock::Gpout2, listener).unwrap();
+        add_listener(Clock::Gpout2, listener).unwrap();
+        configure(Clock::Gpout2, Source::Xosc, XOSC_HZ, 3_000_000).unwrap();
+        assert_eq!(SEEN.load(Ordering::Relaxed), 3_000_000);
+        disable(Clock::Gpout2).unwrap();
+        assert_eq!(SEEN.load(Ordering::Relaxed), 0);
+        remove_listener(Clock::Gpout2, listener);
+        enable(Clock::Gpout2).unwrap();
+        assert_eq!(SEEN.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_too_many_listeners() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        // Distinct bodies so the listeners don't get merged into one function
+        let listeners: [ClockListener; LISTENERS_PER_CLOCK] = [
+            |_, hz| assert_ne!(hz, 1),
+            |_, hz| assert_ne!(hz, 2),
+            |_, hz| assert_ne!(hz, 3),
+            |_, hz| assert_ne!(hz, 4),
+        ];
+        for listener in listeners {
+            add_listener(Clock::Gpout1, listener).unwrap();
+        }
+        assert_eq!(add_listener(Clock::Gpout1, |_, hz| assert_ne!(hz, 5)), Err(ClockError::TooManyListeners));
+        for listener in listeners {
+            remove_listener(Clock::Gpout1, listener);
+        }
+    }
+
+    #[test]
+    fn test_set_sys_hz_updates_derived_clocks() {
+        static SEEN: AtomicUsize = AtomicUsize::new(0);
+        let _clocks = sim::exclusive();
+        sim::reset();
+        clock_set_reported_hz(Clock::Ref, XOSC_HZ);
+        // clk_sys ends at the 150 MHz the other tests expect
+        configure(Clock::Gpout3, Source::ClkSys, 300_000_000, 75_000_000).unwrap();
+        add_listener(Clock::Gpout3, |_, hz| SEEN.store(hz, Ordering::Relaxed)).unwrap();
+        let solution = unsafe { set_sys_hz(150_000_000) }.unwrap();
+        assert_eq!(solution.hz, 150_000_000);
+        assert_eq!(clock_get_hz(Clock::Gpout3), 37_500_000);
+        assert_eq!(SEEN.load(Ordering::Relaxed), 37_500_000);
+    }
+
+    #[test]
+    fn test_set_sys_hz_updates_chained_generators() {
+        static SEEN: AtomicUsize = AtomicUsize::new(0);
+        let _clocks = sim::exclusive();
+        sim::reset();
+        clock_set_reported_hz(Clock::Ref, XOSC_HZ);
+        configure(Clock::Sys, Source::PllSys, 150_000_000, 150_000_000).unwrap();
+        // clk_gpout0 comes before clk_peri in register order
+        configure(Clock::Peri, Source::ClkSys, 150_000_000, 75_000_000).unwrap();
+        configure(Clock::Gpout0, Source::ClkPeri, 75_000_000, 25_000_000).unwrap();
+        add_listener(Clock::Gpout0, |_, hz| SEEN.store(hz, Ordering::Relaxed)).unwrap();
+
+        let solution = unsafe { set_sys_hz(120_000_000) }.unwrap();
+        assert_eq!(clock_get_hz(Clock::Peri), solution.hz / 2);
+        assert_eq!(clock_get_hz(Clock::Gpout0), solution.hz / 6);
+        assert_eq!(SEEN.load(Ordering::Relaxed), solution.hz / 6);
+
+        // Back to the 150 MHz the other tests expect
+        unsafe { set_sys_hz(150_000_000) }.unwrap();
+    }
+
+    #[test]
+    fn test_configure_clk_ref_selects_xosc() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        unsafe { configure_clk_ref() };
+        assert_eq!(sim::peek(CLOCKS.clk_ref_ctrl().addr()) & 0x3, 0x2);
+    }
+
+    #[test]
+    fn test_clk_sys_switches_to_pll_through_aux_mux() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        assert_eq!(configure(Clock::Sys, Source::PllSys, 150_000_000, 150_000_000), Ok(150_000_000));
+        let ctrl = CLOCKS.clk_sys_ctrl().read();
+        assert_eq!(ctrl.src().bits(), 1);
+        assert_eq!(ctrl.auxsrc().bits(), 0);
+        assert_eq!(CLOCKS.clk_sys_selected().read().bits(), 1 << 1);
+        assert_eq!(CLOCKS.clk_sys_div().read().int().bits(), 1);
+    }
+
+    #[test]
+    fn test_gpout_fractional_divider() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        let hz = configure(Clock::Gpout0, Source::Xosc, XOSC_HZ, 5_000_000).unwrap();
+        let div = generator(Clock::Gpout0).div().read();
+        assert_eq!(div.int().bits(), 2);
+        assert_eq!(div.frac().bits(), 0x6666);
+        // 0x2.6666 is the closest 16.16 divider to 2.4
+        assert_eq!(hz, 5_000_012);
+        assert!(is_enabled(Clock::Gpout0));
+        assert_eq!(clock_get_hz(Clock::Gpout0), hz);
+    }
+
+    #[test]
+    fn test_integer_dividers_round_and_check_range() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        // 48 MHz out of 150 MHz needs 3.125, the HSTX generator rounds it to 3
+        assert_eq!(configure(Clock::Hstx, Source::PllSys, 150_000_000, 48_000_000), Ok(50_000_000));
+        assert_eq!(generator(Clock::Hstx).div().read().frac().bits(), 0);
+        // clk_hstx divides by at most 3, 4 doesn't fit its 2 bits integer field
+        assert_eq!(
+            configure(Clock::Hstx, Source::PllSys, 150_000_000, 1_000_000),
+            Err(ClockError::DividerOutOfRange)
+        );
+        // clk_ref's 8 bits integer field tops out at 255
+        assert_eq!(configure(Clock::Ref, Source::Xosc, XOSC_HZ, XOSC_HZ / 255), Ok(XOSC_HZ / 255));
+        assert_eq!(generator(Clock::Ref).div().read().int().bits(), 255);
+        assert_eq!(
+            configure(Clock::Ref, Source::Xosc, XOSC_HZ, XOSC_HZ / 256),
+            Err(ClockError::DividerOutOfRange)
+        );
+        assert_eq!(
+            configure(Clock::Usb, Source::Xosc, XOSC_HZ, 48_000_000),
+            Err(ClockError::FrequencyTooHigh)
+        );
+        assert_eq!(
+            configure(Clock::Peri, Source::Xosc, XOSC_HZ, 0),
+            Err(ClockError::InvalidFrequency)
+        );
+    }
+
+    #[test]
+    fn test_invalid_source() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        assert_eq!(configure(Clock::Peri, Source::ClkRef, XOSC_HZ, XOSC_HZ), Err(ClockError::InvalidSource));
+        assert_eq!(configure(Clock::Ref, Source::PllSys, 150_000_000, 1_000_000), Err(ClockError::InvalidSource));
+    }
+
+    #[test]
+    fn test_disable_and_enable_cache_frequency() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        configure(Clock::Gpout1, Source::Xosc, XOSC_HZ, 1_000_000).unwrap();
+        disable(Clock::Gpout1).unwrap();
+        assert!(!is_enabled(Clock::Gpout1));
+        assert_eq!(clock_get_hz(Clock::Gpout1), 0);
+        enable(Clock::Gpout1).unwrap();
+        assert!(is_enabled(Clock::Gpout1));
+        assert_eq!(clock_get_hz(Clock::Gpout1), 1_000_000);
+        assert_eq!(disable(Clock::Sys), Err(ClockError::NotGateable));
+    }
 }
@@ -0,0 +1,271 @@
+//! PLLs
+//!
+//! Both PLLs multiply the crystal up to a VCO frequency and divide it back down:
+//! `out = xosc / REFDIV * FBDIV / (POSTDIV1 * POSTDIV2)`. [`solve`] searches the dividers
+//! for a target frequency within the datasheet limits, [`configure`] also programs the PLL.
+
+use crate::clocks::ClockError;
+use crate::pac::pll::Pll as PllRegs;
+use crate::pac::{PLL_SYS, PLL_USB};
+use crate::resets::{self, Reset};
+
+/// Lowest reference frequency after REFDIV
+pub const REF_MIN_HZ: usize = 5_000_000;
+/// VCO frequency range
+pub const VCO_MIN_HZ: usize = 750_000_000;
+pub const VCO_MAX_HZ: usize = 1_600_000_000;
+/// REFDIV range
+pub const REFDIV_MAX: u32 = 63;
+/// FBDIV range
+pub const FBDIV_MIN: u32 = 16;
+pub const FBDIV_MAX: u32 = 320;
+/// Range of each post divider
+pub const POSTDIV_MAX: u32 = 7;
+
+/// The two PLLs
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pll {
+    Sys,
+    Usb,
+}
+
+impl Pll {
+    /// The PLL's registers
+    #[inline(always)]
+    pub const fn regs(self) -> PllRegs {
+        match self {
+            Pll::Sys => PLL_SYS,
+            Pll::Usb => PLL_USB,
+        }
+    }
+
+    /// The PLL's reset line
+    #[inline(always)]
+    pub const fn reset_line(self) -> Reset {
+        match self {
+            Pll::Sys => Reset::PllSys,
+            Pll::Usb => Reset::PllUsb,
+        }
+    }
+}
+
+/// Divider settings of a PLL
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PllConfig {
+    pub refdiv: u32,
+    pub fbdiv: u32,
+    pub postdiv1: u32,
+    pub postdiv2: u32,
+}
+
+impl PllConfig {
+    /// VCO frequency in Hz
+    ///
+    /// `xosc_hz`: crystal frequency in Hz
+    pub const fn vco_hz(&self, xosc_hz: usize) -> usize {
+        (xosc_hz as u64 * self.fbdiv as u64 / self.refdiv as u64) as usize
+    }
+
+    /// Output frequency in Hz
+    ///
+    /// `xosc_hz`: crystal frequency in Hz
+    pub const fn out_hz(&self, xosc_hz: usize) -> usize {
+        let div = self.refdiv as u64 * self.postdiv1 as u64 * self.postdiv2 as u64;
+        (xosc_hz as u64 * self.fbdiv as u64 / div) as usize
+    }
+
+    /// Whether every divider and the reference and VCO frequencies are within limits
+    ///
+    /// `xosc_hz`: crystal frequency in Hz
+    pub const fn is_valid(&self, xosc_hz: usize) -> bool {
+        let vco = self.vco_hz(xosc_hz);
+        self.refdiv >= 1 && self.refdiv <= REFDIV_MAX
+            && xosc_hz / self.refdiv as usize >= REF_MIN_HZ
+            && self.fbdiv >= FBDIV_MIN && self.fbdiv <= FBDIV_MAX
+            && self.postdiv1 >= 1 && self.postdiv1 <= POSTDIV_MAX
+            && self.postdiv2 >= 1 && self.postdiv2 <= POSTDIV_MAX
+            && vco >= VCO_MIN_HZ && vco <= VCO_MAX_HZ
+    }
+}
+
+/// A PLL configuration found by [`solve`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PllSolution {
+    pub config: PllConfig,
+    /// Output frequency the configuration achieves, in Hz
+    pub hz: usize,
+    /// Achieved minus requested frequency, in Hz
+    pub error_hz: isize,
+}
+
+/// Searches the PLL dividers closest to a target frequency
+///
+/// Ties go to the smallest REFDIV, then the fastest VCO (lower jitter), then the largest
+/// POSTDIV1, the datasheet recommends POSTDIV1 >= POSTDIV2 to save power.
+/// Returns [`ClockError::PllOutOfRange`] when no configuration is within limits.
+///
+/// `xosc_hz`: crystal frequency in Hz
+/// `target_hz`: requested output frequency in Hz
+pub fn solve(xosc_hz: usize, target_hz: usize) -> Result<PllSolution, ClockError> {
+    let mut best: Option<PllSolution> = None;
+
+    for refdiv in 1..=REFDIV_MAX {
+        if xosc_hz / (refdiv as usize) < REF_MIN_HZ {
+            break;
+        }
+        for postdiv1 in (1..=POSTDIV_MAX).rev() {
+            for postdiv2 in (1..=postdiv1).rev() {
+                // Only the two FBDIVs around the exact ratio can be the closest
+                let num = target_hz as u64 * (refdiv * postdiv1 * postdiv2) as u64;
+                let fbdiv = (num / xosc_hz as u64) as u32;
+                for fbdiv in [fbdiv, fbdiv + 1] {
+                    let config = PllConfig { refdiv, fbdiv, postdiv1, postdiv2 };
+                    if !config.is_valid(xosc_hz) {
+                        continue;
+                    }
+                    let hz = config.out_hz(xosc_hz);
+                    let candidate = PllSolution { config, hz, error_hz: hz as isize - target_hz as isize };
+                    if best.is_none_or(|best| is_better(&candidate, &best, xosc_hz)) {
+                        best = Some(candidate);
+                    }
+                }
+            }
+        }
+    }
+
+    best.ok_or(ClockError::PllOutOfRange)
+}
+
+/// Whether `a` beats `b`, following the order documented on [`solve`]
+fn is_better(a: &PllSolution, b: &PllSolution, xosc_hz: usize) -> bool {
+    let key = |s: &PllSolution| {
+        (
+            s.error_hz.unsigned_abs(),
+            s.config.refdiv,
+            core::cmp::Reverse(s.config.vco_hz(xosc_hz)),
+            core::cmp::Reverse(s.config.postdiv1),
+        )
+    };
+    key(a) < key(b)
+}
+
+/// Reads back the output frequency of a PLL in Hz
+///
+/// `pll`: the PLL to read
+/// `xosc_hz`: crystal frequency in Hz
+pub fn out_hz(pll: Pll, xosc_hz: usize) -> usize {
+    let regs = pll.regs();
+    let prim = regs.prim().read();
+    let config = PllConfig {
+        refdiv: regs.cs().read().refdiv().bits(),
+        fbdiv: regs.fbdiv_int().read().fbdiv_int().bits(),
+        postdiv1: prim.postdiv1().bits(),
+        postdiv2: prim.postdiv2().bits(),
+    };
+    if config.refdiv == 0 || config.postdiv1 == 0 || config.postdiv2 == 0 {
+        return 0;
+    }
+    config.out_hz(xosc_hz)
+}
+
+/// Programs a PLL and waits for it to lock
+///
+/// A running PLL is powered down first, then brought back up with the new dividers.
+///
+/// # Safety
+///
+/// The caller must ensure no clock generator runs from this PLL and the XOSC is running
+///
+/// `pll`: the PLL to program
+/// `config`: its dividers, see [`PllConfig::is_valid`]
+pub unsafe fn init(pll: Pll, config: &PllConfig) {
+    let regs = pll.regs();
+
+    if resets::is_in_reset(pll.reset_line()) {
+        resets::acquire(pll.reset_line()).leak();
+    } else {
+        power_down(pll);
+    }
+
+    regs.cs().write(|w| w.refdiv().bits(config.refdiv));
+    regs.fbdiv_int().write(|w| w.fbdiv_int().bits(config.fbdiv));
+
+    // Power up the VCO and wait for it to lock before enabling the post dividers
+    regs.pwr().clear_bits(|w| w.pd().set_bit().vcopd().set_bit());
+    while !regs.cs().read().lock() {}
+
+    regs.prim().write(|w| w.postdiv1().bits(config.postdiv1).postdiv2().bits(config.postdiv2));
+    regs.pwr().clear_bits(|w| w.postdivpd().set_bit());
+}
+
+/// Powers a PLL down, [`init`] brings it back up
+///
+/// # Safety
+///
+/// The caller must ensure no clock generator runs from this PLL
+///
+/// `pll`: the PLL to stop
+pub unsafe fn power_down(pll: Pll) {
+    pll.regs().pwr().set_bits(|w| w.pd().set_bit().vcopd().set_bit().postdivpd().set_bit());
+}
+
+/// Solves for a target frequency and programs the PLL with the result
+///
+/// Returns the solution so the caller can check the achieved frequency and error.
+///
+/// # Safety
+///
+/// Same as [`init`]
+///
+/// `pll`: the PLL to program
+/// `xosc_hz`: crystal frequency in Hz
+/// `target_hz`: requested output frequency in Hz
+pub unsafe fn configure(pll: Pll, xosc_hz: usize, target_hz: usize) -> Result<PllSolution, ClockError> {
+    let solution = solve(xosc_hz, target_hz)?;
+    init(pll, &solution.config);
+    Ok(solution)
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{configure, out_hz, solve, Pll, PllConfig};
+    use crate::backend::sim;
+    use crate::clocks::{ClockError, XOSC_HZ};
+    use crate::pac::PLL_USB;
+
+    #[test]
+    fn test_solve_default_frequencies() {
+        let sys = solve(XOSC_HZ, 150_000_000).unwrap();
+        assert_eq!(sys.config, PllConfig { refdiv: 1, fbdiv: 125, postdiv1: 5, postdiv2: 2 });
+        assert_eq!(sys.error_hz, 0);
+        let usb = solve(XOSC_HZ, 48_000_000).unwrap();
+        assert_eq!(usb.config, PllConfig { refdiv: 1, fbdiv: 120, postdiv1: 6, postdiv2: 5 });
+    }
+
+    #[test]
+    fn test_solve_overclock_and_inexact() {
+        let fast = solve(XOSC_HZ, 200_000_000).unwrap();
+        assert_eq!(fast.hz, 200_000_000);
+        assert!(fast.config.is_valid(XOSC_HZ));
+        // 133 MHz isn't reachable from a 12 MHz crystal, the closest is reported with its error
+        let odd = solve(XOSC_HZ, 133_000_000).unwrap();
+        assert_eq!(odd.error_hz, odd.hz as isize - 133_000_000);
+        assert!(odd.error_hz.unsigned_abs() < 200_000);
+    }
+
+    #[test]
+    fn test_solve_out_of_range() {
+        assert_eq!(solve(XOSC_HZ, 5_000_000), Err(ClockError::PllOutOfRange));
+        assert_eq!(solve(XOSC_HZ, 2_000_000_000), Err(ClockError::PllOutOfRange));
+    }
+
+    #[test]
+    fn test_configure_programs_pll_usb() {
+        sim::reset();
+        let solution = unsafe { configure(Pll::Usb, XOSC_HZ, 48_000_000) }.unwrap();
+        assert_eq!(solution.hz, 48_000_000);
+        assert_eq!(out_hz(Pll::Usb, XOSC_HZ), 48_000_000);
+        // Only the dividers' power-down bits are cleared, DSMPD stays set for integer mode
+        assert_eq!(sim::peek(PLL_USB.pwr().addr()), 0x04);
+    }
+}
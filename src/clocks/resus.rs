@@ -0,0 +1,264 @@
+//! clk_sys resuscitation
+//!
+//! If clk_sys stops, because PLL_SYS lost lock or the crystal died, the resus circuit notices
+//! within a timeout counted in clk_ref cycles and forces clk_sys onto clk_ref. The CLOCKS_IRQ
+//! handler installed by [`enable`] then makes that switch official: clk_ref moves to the ROSC if
+//! the XOSC is gone, clk_sys is configured from clk_ref, the resus is cleared and the event is
+//! recorded for [`status`] and handed to the callback.
+//!
+//! A glitchless mux can't always leave a dead clock, so the handler only polls each switch for
+//! a while. If clk_ref or clk_sys doesn't switch in time, the hardware keeps clk_sys on clk_ref,
+//! the resus interrupt is masked so the handler doesn't fire again and again, and the event is
+//! recorded with a `recovered_hz` of 0.
+//!
+//! ```ignore
+//! unsafe { resus::enable(resus::DEFAULT_TIMEOUT, Some(log_clock_failure)) };
+//! ```
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::clocks::{clock_get_hz, configure_polling, rosc, Clock, ClockError, Source};
+use crate::interrupts::{nvic_enable, set_irq_handler, Interrupt};
+use crate::pac::{CLOCKS, XOSC};
+
+/// Largest timeout, in clk_ref cycles
+pub const TIMEOUT_MAX: u32 = 0xff;
+
+/// SELECTED reads the handler allows per switch, a running clock switches within a few cycles
+const SWITCH_POLLS: usize = 1000;
+
+/// Timeout of [`enable`] catching any clk_sys slower than about 100 kHz with a 12 MHz clk_ref
+pub const DEFAULT_TIMEOUT: u32 = TIMEOUT_MAX;
+
+/// What happened during a resus
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ResusEvent {
+    /// clk_sys frequency before it stopped, in Hz
+    pub lost_hz: usize,
+    /// The XOSC wasn't stable anymore, clk_ref was moved to the ROSC
+    pub xosc_failed: bool,
+    /// clk_sys frequency after the recovery in Hz, 0 if a switch timed out
+    pub recovered_hz: usize,
+}
+
+/// Snapshot of the resus state, see [`status`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ResusStatus {
+    /// The resus circuit is armed
+    pub enabled: bool,
+    /// The hardware is currently forcing clk_sys onto clk_ref
+    pub resussed: bool,
+    /// Recoveries since boot
+    pub events: usize,
+    /// The latest recovery
+    pub last: Option<ResusEvent>,
+}
+
+/// Called from the CLOCKS_IRQ handler once clk_sys was recovered
+pub type ResusCallback = fn(event: ResusEvent);
+
+/// Callback as a function address, 0 when there is none
+static CALLBACK: AtomicUsize = AtomicUsize::new(0);
+
+static EVENTS: AtomicUsize = AtomicUsize::new(0);
+static LAST_LOST_HZ: AtomicUsize = AtomicUsize::new(0);
+static LAST_RECOVERED_HZ: AtomicUsize = AtomicUsize::new(0);
+static LAST_XOSC_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Shortest timeout still detecting a clk_sys slower than `min_sys_hz`
+///
+/// The datasheet asks for at least two cycles of the slowest clk_sys per timeout.
+///
+/// `ref_hz`: clk_ref frequency in Hz
+/// `min_sys_hz`: slowest clk_sys that still counts as running, in Hz
+pub const fn timeout_for(ref_hz: usize, min_sys_hz: usize) -> u32 {
+    (2 * ref_hz).div_ceil(min_sys_hz) as u32
+}
+
+/// Arms the resus circuit and installs its CLOCKS_IRQ handler
+///
+/// # Safety
+///
+/// This function edits the vector table in ram, see [`set_irq_handler`], and the handler
+/// reconfigures clk_ref and clk_sys behind the back of whatever runs
+///
+/// `timeout`: clk_ref cycles without a clk_sys edge before the resus fires, 1 to
+/// [`TIMEOUT_MAX`], see [`timeout_for`]
+/// `callback`: called after every recovery
+pub unsafe fn enable(timeout: u32, callback: Option<ResusCallback>) {
+    CALLBACK.store(callback.map_or(0, |callback| callback as usize), Ordering::Release);
+
+    set_irq_handler(Interrupt::CLOCKS_IRQ, on_irq);
+    CLOCKS.inte().set_bits(|w| w.clk_sys_resus().set_bit());
+    nvic_enable(Interrupt::CLOCKS_IRQ);
+
+    CLOCKS.clk_sys_resus_ctrl().write_with_zero(|w| {
+        w.enable().set_bit().timeout().bits(timeout.clamp(1, TIMEOUT_MAX))
+    });
+}
+
+/// Disarms the resus circuit, the handler stays installed but never fires
+pub fn disable() {
+    CLOCKS.clk_sys_resus_ctrl().clear_bits(|w| w.enable().set_bit());
+    CLOCKS.inte().clear_bits(|w| w.clk_sys_resus().set_bit());
+}
+
+/// Forces a resus as if clk_sys had stopped, to test the recovery path
+pub fn force() {
+    CLOCKS.clk_sys_resus_ctrl().set_bits(|w| w.frce().set_bit());
+    CLOCKS.clk_sys_resus_ctrl().clear_bits(|w| w.frce().set_bit());
+}
+
+/// Current state and history of the resus
+pub fn status() -> ResusStatus {
+    let events = EVENTS.load(Ordering::Acquire);
+    ResusStatus {
+        enabled: CLOCKS.clk_sys_resus_ctrl().read().enable(),
+        resussed: CLOCKS.clk_sys_resus_status().read().resussed(),
+        events,
+        last: (events > 0).then(|| ResusEvent {
+            lost_hz: LAST_LOST_HZ.load(Ordering::Relaxed),
+            xosc_failed: LAST_XOSC_FAILED.load(Ordering::Relaxed),
+            recovered_hz: LAST_RECOVERED_HZ.load(Ordering::Relaxed),
+        }),
+    }
+}
+
+/// CLOCKS_IRQ handler
+fn on_irq() {
+    if CLOCKS.ints().read().clk_sys_resus() {
+        recover();
+    }
+}
+
+/// Moves clk_sys onto a running source and clears the resus
+fn recover() -> ResusEvent {
+    let lost_hz = clock_get_hz(Clock::Sys);
+
+    // clk_ref is only safe as long as the crystal behind it runs
+    let xosc_failed = !XOSC.status().read().stable();
+    let recovered_hz = match sys_onto_ref(xosc_failed) {
+        Ok(hz) => {
+            // clk_sys is fixed, the resus can let go
+            CLOCKS.clk_sys_resus_ctrl().set_bits(|w| w.clear().set_bit());
+            CLOCKS.clk_sys_resus_ctrl().clear_bits(|w| w.clear().set_bit());
+            hz
+        }
+        Err(_) => {
+            // The hardware holds clk_sys on clk_ref, stop the interrupt from firing forever
+            CLOCKS.inte().clear_bits(|w| w.clk_sys_resus().set_bit());
+            0
+        }
+    };
+
+    let event = ResusEvent { lost_hz, xosc_failed, recovered_hz };
+    LAST_LOST_HZ.store(lost_hz, Ordering::Relaxed);
+    LAST_XOSC_FAILED.store(xosc_failed, Ordering::Relaxed);
+    LAST_RECOVERED_HZ.store(recovered_hz, Ordering::Relaxed);
+    EVENTS.fetch_add(1, Ordering::Release);
+
+    let raw = CALLBACK.load(Ordering::Acquire);
+    if raw != 0 {
+        // SAFETY: a non-zero address is always a `ResusCallback` stored by `enable`
+        let callback = unsafe { core::mem::transmute::<usize, ResusCallback>(raw) };
+        callback(event);
+    }
+    event
+}
+
+/// Runs clk_sys from clk_ref, moving clk_ref to the ROSC first if the crystal is gone
+///
+/// `xosc_failed`: whether the XOSC stopped
+fn sys_onto_ref(xosc_failed: bool) -> Result<usize, ClockError> {
+    if xosc_failed {
+        rosc::enable();
+        configure_polling(Clock::Ref, Source::RoscPh, rosc::hz(), rosc::hz(), SWITCH_POLLS)?;
+    }
+    let ref_hz = clock_get_hz(Clock::Ref);
+    configure_polling(Clock::Sys, Source::ClkRef, ref_hz, ref_hz, SWITCH_POLLS)
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{enable, on_irq, status, timeout_for, ResusEvent, DEFAULT_TIMEOUT};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use crate::backend::sim;
+    use crate::clocks::{clock_get_hz, clock_set_reported_hz, generator, rosc, Clock};
+    use crate::pac::{CLOCKS, ROSC, XOSC};
+
+    #[test]
+    fn test_timeout_for_slowest_sys() {
+        assert_eq!(timeout_for(12_000_000, 100_000), 240);
+        assert_eq!(timeout_for(12_000_000, 7_000_000), 4);
+    }
+
+    #[test]
+    fn test_enable_arms_resus() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        unsafe { enable(DEFAULT_TIMEOUT, None) };
+        assert_eq!(sim::peek(CLOCKS.clk_sys_resus_ctrl().addr()), 0x1ff);
+        assert!(CLOCKS.inte().read().clk_sys_resus());
+        assert!(status().enabled);
+    }
+
+    #[test]
+    fn test_irq_recovers_sys_onto_clk_ref() {
+        static SEEN: AtomicUsize = AtomicUsize::new(0);
+        fn callback(event: ResusEvent) {
+            SEEN.store(event.recovered_hz, Ordering::Relaxed);
+        }
+
+        let _clocks = sim::exclusive();
+        sim::reset();
+        unsafe { enable(DEFAULT_TIMEOUT, Some(callback)) };
+        let events = status().events;
+
+        // The crystal runs, PLL_SYS died
+        XOSC.status().write(|w| w.stable().set_bit());
+        CLOCKS.ints().write(|w| w.clk_sys_resus().set_bit());
+        on_irq();
+
+        assert_eq!(generator(Clock::Sys).ctrl().read().src().bits(), 0);
+        assert_eq!(clock_get_hz(Clock::Sys), 12_000_000);
+        assert_eq!(SEEN.load(Ordering::Relaxed), 12_000_000);
+        let after = status();
+        assert_eq!(after.events, events + 1);
+        assert_eq!(after.last.map(|last| last.xosc_failed), Some(false));
+
+        // Dead crystal: clk_ref falls back on the ROSC
+        XOSC.status().write(|w| w.bits(0));
+        ROSC.status().write(|w| w.stable().set_bit());
+        on_irq();
+        assert_eq!(clock_get_hz(Clock::Ref), rosc::hz());
+        assert_eq!(clock_get_hz(Clock::Sys), rosc::hz());
+        assert_eq!(status().last.map(|last| last.xosc_failed), Some(true));
+
+        clock_set_reported_hz(Clock::Ref, 12_000_000);
+        clock_set_reported_hz(Clock::Sys, 150_000_000);
+    }
+
+    #[test]
+    fn test_irq_gives_up_on_a_stuck_switch() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        unsafe { enable(DEFAULT_TIMEOUT, None) };
+        let events = status().events;
+
+        // Dead crystal, and clk_ref's glitchless mux never lets go of it
+        XOSC.status().write(|w| w.bits(0));
+        ROSC.status().write(|w| w.stable().set_bit());
+        sim::on_read(generator(Clock::Ref).selected().addr(), |_, _| 1 << 2);
+        // The resus isn't cleared, clk_sys stays where the hardware put it
+        sim::on_write(CLOCKS.clk_sys_resus_ctrl().addr(), |_, val| {
+            assert_eq!(val & 1 << 16, 0, "resus cleared after a failed switch")
+        });
+        CLOCKS.ints().write(|w| w.clk_sys_resus().set_bit());
+        on_irq();
+
+        let after = status();
+        assert_eq!(after.events, events + 1);
+        assert_eq!(after.last.map(|last| last.recovered_hz), Some(0));
+        assert!(!CLOCKS.inte().read().clk_sys_resus());
+    }
+}
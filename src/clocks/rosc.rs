@@ -0,0 +1,183 @@
+//! Ring oscillator
+//!
+//! The ROSC is a chain of inverters that runs as soon as the chip is powered, no crystal needed.
+//! Its frequency depends on the number of stages in the ring (the frequency range), how hard each
+//! stage is driven and the output divider, and drifts with process, voltage and temperature, so
+//! [`measure`] it against the XOSC when the exact frequency matters.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::clocks::{fc0, Source};
+use crate::pac::rosc::{CtrlEnable, CtrlFreqRange, DivDiv, FreqaPasswd, FreqbPasswd};
+use crate::pac::ROSC;
+
+/// Output frequency at reset, within a factor of two or so on any given chip
+pub const NOMINAL_HZ: usize = 11_000_000;
+
+/// Output divider range
+pub const DIV_MAX: u32 = 128;
+
+/// Largest drive strength of a stage
+pub const DRIVE_MAX: u8 = 7;
+
+/// Last measured or estimated frequency
+static ROSC_FREQ: AtomicUsize = AtomicUsize::new(NOMINAL_HZ);
+
+/// Number of inverters in the ring, fewer stages run faster
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Range {
+    /// 8 stages
+    Low,
+    /// 6 stages
+    Medium,
+    /// 4 stages
+    High,
+}
+
+impl Range {
+    /// Stages the range puts in the ring, their drive strengths are the first of [`set_drive`]'s
+    pub const fn stages(self) -> usize {
+        match self {
+            Range::Low => 8,
+            Range::Medium => 6,
+            Range::High => 4,
+        }
+    }
+
+    const fn freq_range(self) -> CtrlFreqRange {
+        match self {
+            Range::Low => CtrlFreqRange::Low,
+            Range::Medium => CtrlFreqRange::Medium,
+            Range::High => CtrlFreqRange::High,
+        }
+    }
+}
+
+/// Starts the ROSC and waits until it's stable
+pub fn enable() {
+    ROSC.ctrl().modify(|_, w| w.enable().variant(CtrlEnable::Enable));
+    while !ROSC.status().read().stable() {}
+}
+
+/// Stops the ROSC
+///
+/// # Safety
+///
+/// The caller must ensure no clock generator runs from the ROSC
+pub unsafe fn disable() {
+    ROSC.ctrl().modify(|_, w| w.enable().variant(CtrlEnable::Disable));
+}
+
+/// Whether the ROSC is running
+pub fn is_enabled() -> bool {
+    ROSC.status().read().enabled()
+}
+
+/// Selects the number of stages in the ring
+///
+/// The datasheet only allows going up a range while the ROSC runs, going down can make the
+/// output glitch: move the clocks off the ROSC first.
+///
+/// `range`: the frequency range
+pub fn set_range(range: Range) {
+    ROSC.ctrl().modify(|_, w| w.freq_range().variant(range.freq_range()));
+}
+
+/// Sets the drive strength of each stage, stronger stages switch faster
+///
+/// Only the first [`Range::stages`] strengths matter in a given range. Raise them a step at a
+/// time, large jumps can overshoot the frequency the rest of the chip is timed for.
+///
+/// `drive`: strength of stages 0 to 7, 0 to [`DRIVE_MAX`]
+pub fn set_drive(drive: [u8; 8]) {
+    let ds = |stage: usize| u32::from(drive[stage].min(DRIVE_MAX));
+    ROSC.freqa().write_with_zero(|w| {
+        w.passwd().variant(FreqaPasswd::Pass)
+            .ds0().bits(ds(0)).ds1().bits(ds(1)).ds2().bits(ds(2)).ds3().bits(ds(3))
+    });
+    ROSC.freqb().write_with_zero(|w| {
+        w.passwd().variant(FreqbPasswd::Pass)
+            .ds4().bits(ds(4)).ds5().bits(ds(5)).ds6().bits(ds(6)).ds7().bits(ds(7))
+    });
+}
+
+/// Sets the output divider, which divides the ring's frequency down to the ROSC output
+///
+/// `div`: 1 to [`DIV_MAX`]
+pub fn set_divider(div: u32) {
+    let div = div.clamp(1, DIV_MAX) % DIV_MAX;
+    ROSC.div().write(|w| w.div().bits(DivDiv::Pass as u32 + div));
+}
+
+/// The output divider
+pub fn divider() -> u32 {
+    match ROSC.div().read().div().bits().wrapping_sub(DivDiv::Pass as u32) {
+        0 => DIV_MAX,
+        div => div,
+    }
+}
+
+/// One bit sampled from the ring, not cryptographically random but good enough for seeding
+pub fn random_bit() -> bool {
+    ROSC.randombit().read().randombit()
+}
+
+/// 32 bits sampled from the ring one at a time, see [`random_bit`]
+pub fn random_u32() -> u32 {
+    (0..32).fold(0, |acc, _| (acc << 1) | random_bit() as u32)
+}
+
+/// Measures the ROSC output with the frequency counter and remembers it for [`hz`]
+///
+/// clk_ref must run from the XOSC, the counter's time base would drift with the ROSC otherwise.
+pub fn measure() -> usize {
+    let hz = fc0::measure_hz(Source::Rosc);
+    ROSC_FREQ.store(hz, Ordering::Relaxed);
+    hz
+}
+
+/// Last frequency from [`measure`], or [`NOMINAL_HZ`] if it was never measured
+#[inline(always)]
+pub fn hz() -> usize {
+    ROSC_FREQ.load(Ordering::Relaxed)
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{divider, random_u32, set_divider, set_drive, set_range, Range, DIV_MAX};
+    use crate::backend::sim;
+    use crate::pac::rosc::CtrlFreqRange;
+    use crate::pac::ROSC;
+
+    #[test]
+    fn test_range_and_drive() {
+        sim::reset();
+        set_range(Range::Medium);
+        assert_eq!(ROSC.ctrl().read().freq_range().variant(), Some(CtrlFreqRange::Medium));
+        set_drive([1, 2, 3, 4, 5, 6, 7, 9]);
+        assert_eq!(sim::peek(ROSC.freqa().addr()), 0x9696_4321);
+        assert_eq!(sim::peek(ROSC.freqb().addr()), 0x9696_7765);
+    }
+
+    #[test]
+    fn test_divider_wraps_128_to_zero() {
+        sim::reset();
+        set_divider(8);
+        assert_eq!(sim::peek(ROSC.div().addr()), 0xaa08);
+        assert_eq!(divider(), 8);
+        set_divider(DIV_MAX);
+        assert_eq!(sim::peek(ROSC.div().addr()), 0xaa00);
+        assert_eq!(divider(), DIV_MAX);
+    }
+
+    #[test]
+    fn test_random_u32_samples_each_bit() {
+        sim::reset();
+        // Alternate 1, 0, 1, 0...
+        sim::on_read(ROSC.randombit().addr(), |addr, stored| {
+            sim::poke(addr, stored ^ 1);
+            stored ^ 1
+        });
+        assert_eq!(random_u32(), 0xaaaa_aaaa);
+    }
+}
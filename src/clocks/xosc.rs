@@ -0,0 +1,103 @@
+//! Crystal oscillator
+//!
+//! The XOSC drives the crystal fitted to the board, 12 MHz on most boards. Its frequency is
+//! recorded at init so everything derived from it (clk_ref, the PLLs, clk_peri) uses the
+//! configured crystal rather than [`XOSC_HZ`](crate::clocks::XOSC_HZ).
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::clocks::{clock_set_reported_hz, Clock, XOSC_HZ};
+use crate::pac::xosc::{CtrlEnable, CtrlFreqRange};
+use crate::pac::XOSC;
+
+/// Crystal frequencies the XOSC supports
+pub const XOSC_MIN_HZ: usize = 1_000_000;
+pub const XOSC_MAX_HZ: usize = 50_000_000;
+
+/// Largest STARTUP.DELAY
+pub const STARTUP_DELAY_MAX: u32 = (1 << 14) - 1;
+
+/// Configured crystal frequency
+static XOSC_FREQ: AtomicUsize = AtomicUsize::new(XOSC_HZ);
+
+/// Frequency range covering a crystal, the lowest one when two overlap
+///
+/// `xosc_hz`: crystal frequency in Hz
+pub const fn range_for(xosc_hz: usize) -> Option<CtrlFreqRange> {
+    match xosc_hz {
+        XOSC_MIN_HZ..=15_000_000 => Some(CtrlFreqRange::_1_15mhz),
+        15_000_001..=30_000_000 => Some(CtrlFreqRange::_10_30mhz),
+        30_000_001..=XOSC_MAX_HZ => Some(CtrlFreqRange::_25_60mhz),
+        _ => None,
+    }
+}
+
+/// Whether a frequency range covers a crystal
+///
+/// `range`: the XOSC frequency range
+/// `xosc_hz`: crystal frequency in Hz
+pub const fn range_covers(range: CtrlFreqRange, xosc_hz: usize) -> bool {
+    let (min, max) = match range {
+        CtrlFreqRange::_1_15mhz => (1_000_000, 15_000_000),
+        CtrlFreqRange::_10_30mhz => (10_000_000, 30_000_000),
+        CtrlFreqRange::_25_60mhz => (25_000_000, 60_000_000),
+        CtrlFreqRange::_40_100mhz => (40_000_000, 100_000_000),
+    };
+    xosc_hz >= min && xosc_hz <= max
+}
+
+/// STARTUP.DELAY waiting at least `ms` milliseconds, it counts in units of 256 crystal cycles
+///
+/// `xosc_hz`: crystal frequency in Hz
+/// `ms`: startup time in milliseconds
+pub const fn startup_delay(xosc_hz: usize, ms: u32) -> u32 {
+    (((xosc_hz / 1000) as u64 * ms as u64 + 128) / 256) as u32
+}
+
+/// Configured crystal frequency in Hz
+#[inline(always)]
+pub fn hz() -> usize {
+    XOSC_FREQ.load(Ordering::Relaxed)
+}
+
+/// Starts the XOSC and waits until it's stable
+///
+/// # Safety
+///
+/// The caller must ensure the XOSC isn't already initialized
+///
+/// `xosc_hz`: crystal frequency in Hz
+/// `range`: frequency range covering the crystal, see [`range_for`]
+/// `startup_delay`: STARTUP.DELAY, see [`startup_delay`]
+pub unsafe fn init(xosc_hz: usize, range: CtrlFreqRange, startup_delay: u32) {
+    XOSC.startup().modify(|_, w| w.delay().bits(startup_delay));
+    XOSC.ctrl().modify(|_, w| w.freq_range().variant(range).enable().variant(CtrlEnable::Enable));
+
+    while !XOSC.status().read().stable() {}
+    // XOSC is now running
+    XOSC_FREQ.store(xosc_hz, Ordering::Relaxed);
+    clock_set_reported_hz(Clock::Ref, xosc_hz); // optional here, but convenient
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{range_covers, range_for, startup_delay};
+    use crate::pac::xosc::CtrlFreqRange;
+
+    #[test]
+    fn test_startup_delay_from_ms() {
+        assert_eq!(startup_delay(12_000_000, 10), 469);
+        assert_eq!(startup_delay(12_000_000, 1), 47);
+        assert_eq!(startup_delay(40_000_000, 50), 7813);
+    }
+
+    #[test]
+    fn test_range_for_crystal() {
+        assert_eq!(range_for(12_000_000), Some(CtrlFreqRange::_1_15mhz));
+        assert_eq!(range_for(24_000_000), Some(CtrlFreqRange::_10_30mhz));
+        assert_eq!(range_for(40_000_000), Some(CtrlFreqRange::_25_60mhz));
+        assert_eq!(range_for(60_000_000), None);
+        assert!(range_covers(CtrlFreqRange::_10_30mhz, 12_000_000));
+        assert!(!range_covers(CtrlFreqRange::_1_15mhz, 16_000_000));
+    }
+}
@@ -0,0 +1,285 @@
+//! Pins numbered at runtime
+//!
+//! [`AnyPin`] forgets the pin number of a [`Pin`], or takes a pin numbered at runtime, so pins
+//! from a board configuration or of an array of LEDs share one type. [`PinGroup`] drives several of them with single SIO writes, so
+//! every pin of a parallel bus changes on the same clock edge.
+//!
+//! ```ignore
+//! let leds = PinGroup::new([Pin::<2>::take().into(), Pin::<3>::take().into()]).ok().unwrap();
+//! leds.write(0b0100);
+//! ```
+
+use core::marker::PhantomData;
+
+use crate::gpio::irq::{self, Core, Events, GpioCallback};
+use crate::gpio::mode::{Output, OutputType, PinMode, PushPull};
+use crate::gpio::pad::Pad;
+use crate::gpio::sio::SioBank;
+use crate::gpio::{Claim, Pin, PIN_COUNT};
+use crate::Valid;
+
+/// A GPIO in mode `M` whose number is only known at runtime
+pub struct AnyPin<M = Output<PushPull>> {
+    claim: Claim,
+    _mode: PhantomData<M>,
+}
+
+impl<const N: usize, M> From<Pin<N, M>> for AnyPin<M>
+where
+    Pin<N>: Valid,
+{
+    fn from(pin: Pin<N, M>) -> Self {
+        AnyPin { claim: pin.claim, _mode: PhantomData }
+    }
+}
+
+impl<M: PinMode> AnyPin<M> {
+    /// Takes GPIO `pin` and configures it for its mode
+    ///
+    /// Returns `None` if the package doesn't have the GPIO, if it's already taken or if it can't
+    /// be routed to the mode's function.
+    ///
+    /// `pin`: the GPIO number
+    pub fn try_take(pin: usize) -> Option<Self> {
+        if pin >= PIN_COUNT || !M::FUNCSEL.available_on(pin) {
+            return None;
+        }
+        let claim = Claim::new(pin)?;
+        M::configure(pin);
+        Some(Self { claim, _mode: PhantomData })
+    }
+}
+
+impl<M> AnyPin<M> {
+    /// The GPIO number
+    pub fn pin(&self) -> usize {
+        self.claim.pin
+    }
+
+    /// The SIO registers holding the pin
+    pub fn bank(&self) -> SioBank {
+        SioBank::of(self.claim.pin)
+    }
+
+    /// The pin's bit in its SIO registers
+    pub fn mask(&self) -> u32 {
+        SioBank::mask(self.claim.pin)
+    }
+
+    /// Reconfigures the pin for another mode, if the pin supports it
+    ///
+    /// Returns the pin unchanged when it can't be routed to the mode's function.
+    pub fn try_into_mode<M2: PinMode>(self) -> Result<AnyPin<M2>, Self> {
+        if !M2::FUNCSEL.available_on(self.claim.pin) {
+            return Err(self);
+        }
+        M2::configure(self.claim.pin);
+        Ok(AnyPin { claim: self.claim, _mode: PhantomData })
+    }
+
+    /// The pin's pad, for its electrical settings
+    pub fn pad(&self) -> Pad {
+        Pad::new(self.claim.pin)
+    }
+
+    /// Level on the pin, 1 for high and 0 for low
+    pub fn value(&self) -> u32 {
+        (self.bank().input() >> (self.claim.pin % 32)) & 1
+    }
+
+    /// Whether the pin is high
+    pub fn is_high(&self) -> bool {
+        self.value() != 0
+    }
+
+    /// Whether the pin is low
+    pub fn is_low(&self) -> bool {
+        self.value() == 0
+    }
+
+    /// Interrupts the calling core on `events` and runs `callback` from the dispatcher
+    ///
+    /// `events`: the events to enable
+    /// `callback`: the handler
+    pub fn enable_interrupt(&self, events: Events, callback: GpioCallback) {
+        irq::set_callback(self.claim.pin, callback);
+        irq::enable(self.claim.pin, events, Core::current());
+    }
+
+    /// Stops interrupting the calling core on `events`
+    ///
+    /// `events`: the events to disable
+    pub fn disable_interrupt(&self, events: Events) {
+        irq::disable(self.claim.pin, events, Core::current());
+    }
+}
+
+impl<T: OutputType> AnyPin<Output<T>> {
+    /// Set the pin high
+    pub fn set(&self) {
+        T::set(self.bank(), self.mask());
+    }
+
+    /// Set the pin low
+    pub fn clear(&self) {
+        T::clear(self.bank(), self.mask());
+    }
+
+    /// Toggle the pin
+    pub fn toggle(&self) {
+        T::toggle(self.bank(), self.mask());
+    }
+}
+
+/// `LEN` pins in mode `M` read and driven together
+pub struct PinGroup<const LEN: usize, M = Output<PushPull>> {
+    pins: [AnyPin<M>; LEN],
+    bank: SioBank,
+    mask: u32,
+}
+
+impl<const LEN: usize, M> PinGroup<LEN, M> {
+    /// Groups pins, which must all share their SIO registers: GPIO0 to 31 or GPIO32 to 47
+    ///
+    /// Returns the pins if they don't.
+    ///
+    /// `pins`: the members
+    pub fn new(pins: [AnyPin<M>; LEN]) -> Result<Self, [AnyPin<M>; LEN]> {
+        let bank = pins.first().map_or(SioBank::Lo, AnyPin::bank);
+        if pins.iter().any(|pin| pin.bank() != bank) {
+            return Err(pins);
+        }
+        let mask = pins.iter().fold(0, |mask, pin| mask | pin.mask());
+        Ok(Self { pins, bank, mask })
+    }
+
+    /// The SIO registers holding the members
+    pub fn bank(&self) -> SioBank {
+        self.bank
+    }
+
+    /// The members' bits in their SIO registers
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    /// The members
+    pub fn pins(&self) -> &[AnyPin<M>; LEN] {
+        &self.pins
+    }
+
+    /// Breaks the group up
+    pub fn into_pins(self) -> [AnyPin<M>; LEN] {
+        self.pins
+    }
+
+    /// Levels of the members in one read, as SIO bits outside the group read 0
+    pub fn read(&self) -> u32 {
+        self.bank.input() & self.mask
+    }
+}
+
+impl<const LEN: usize, T: OutputType> PinGroup<LEN, Output<T>> {
+    /// Sets the members in `mask` high with one write
+    ///
+    /// `mask`: SIO bits, those outside the group are ignored
+    pub fn set(&self, mask: u32) {
+        T::set(self.bank, mask & self.mask);
+    }
+
+    /// Sets the members in `mask` low with one write
+    ///
+    /// `mask`: SIO bits, those outside the group are ignored
+    pub fn clear(&self, mask: u32) {
+        T::clear(self.bank, mask & self.mask);
+    }
+
+    /// Toggles the members in `mask` with one write
+    ///
+    /// `mask`: SIO bits, those outside the group are ignored
+    pub fn toggle(&self, mask: u32) {
+        T::toggle(self.bank, mask & self.mask);
+    }
+
+    /// Drives every member at once, high where `value` has its bit set
+    ///
+    /// The members that change are toggled with a single XOR write.
+    ///
+    /// `value`: SIO bits, those outside the group are ignored
+    pub fn write(&self, value: u32) {
+        let changed = (T::state(self.bank) ^ value) & self.mask;
+        if changed != 0 {
+            T::toggle(self.bank, changed);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{AnyPin, PinGroup};
+    use crate::backend::sim;
+    use crate::gpio::mode::{Function, Hstx, Input, Output, PullDown, PushPull};
+    use crate::gpio::{is_taken, Pin, PIN_COUNT};
+    use crate::pac::SIO;
+
+    #[test]
+    fn test_any_pin_from_pin() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        let leds: [AnyPin; 2] = [Pin::<10>::take().into(), Pin::<11>::take().into()];
+        for led in &leds {
+            led.set();
+        }
+        assert_eq!(SIO.gpio_out().read().bits(), 0b11 << 10);
+        assert_eq!(leds[1].pin(), 11);
+
+        let [first, second] = leds;
+        let input = first.try_into_mode::<Input<PullDown>>().ok().unwrap();
+        assert_eq!(SIO.gpio_oe().read().bits(), 1 << 11);
+        sim::poke(SIO.gpio_in().addr(), 1 << 10);
+        assert!(input.is_high());
+        // HSTX only exists on GPIO12 to 19
+        assert!(second.try_into_mode::<Function<Hstx>>().is_err());
+    }
+
+    #[test]
+    fn test_try_take_numbered_at_runtime() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        let led = AnyPin::<Output<PushPull>>::try_take(14).unwrap();
+        assert_eq!(SIO.gpio_oe().read().bits(), 1 << 14);
+        assert!(AnyPin::<Output<PushPull>>::try_take(14).is_none());
+        assert!(AnyPin::<Output<PushPull>>::try_take(PIN_COUNT).is_none());
+        // HSTX only exists on GPIO12 to 19
+        assert!(AnyPin::<Function<Hstx>>::try_take(20).is_none());
+        assert!(!is_taken(20));
+
+        drop(led);
+        assert!(AnyPin::<Function<Hstx>>::try_take(14).is_some());
+    }
+
+    #[test]
+    fn test_group_writes_once() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        let bus = PinGroup::new([
+            Pin::<4>::take().into(),
+            Pin::<5>::take().into(),
+            Pin::<6>::take().into(),
+        ])
+        .ok()
+        .unwrap();
+        assert_eq!(bus.mask(), 0b111 << 4);
+        bus.set(0b011 << 4);
+        assert_eq!(SIO.gpio_out().read().bits(), 0b011 << 4);
+
+        // A single XOR write takes the bus from 0b011 to 0b100, whatever pin 7 says
+        sim::on_write(SIO.gpio_out_set().addr(), |_, _| panic!("bus written twice"));
+        sim::on_write(SIO.gpio_out_clr().addr(), |_, _| panic!("bus written twice"));
+        bus.write(0b1100 << 4);
+        assert_eq!(SIO.gpio_out().read().bits(), 0b100 << 4);
+
+        sim::poke(SIO.gpio_in().addr(), 0b1010 << 4);
+        assert_eq!(bus.read(), 0b010 << 4);
+    }
+}
@@ -0,0 +1,293 @@
+//! GPIO interrupts
+//!
+//! Every GPIO has four events, level low, level high, falling edge and rising edge, which each
+//! core enables separately. [`install`] puts a dispatcher on IO_IRQ_BANK0 of the calling core:
+//! it acknowledges the edges it sees and calls the callback registered for each pin.
+//!
+//! Pins past [`PIN_COUNT`] don't exist on the package, the functions ignore them.
+//!
+//! ```ignore
+//! irq::set_callback(6, on_button);
+//! irq::enable(6, Events::EDGE_FALL, Core::current());
+//! unsafe { irq::install() };
+//! ```
+
+use core::ops::BitOr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::interrupts::{nvic_enable, set_irq_handler, Interrupt};
+use crate::gpio::PIN_COUNT;
+use crate::pac::{IO_BANK0, SIO};
+
+/// Events of a GPIO for one core, as the 4 bits the hardware packs per pin
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Events(u32);
+
+impl Events {
+    pub const NONE: Events = Events(0);
+    /// Pin is low, until it's not
+    pub const LEVEL_LOW: Events = Events(1 << 0);
+    /// Pin is high, until it's not
+    pub const LEVEL_HIGH: Events = Events(1 << 1);
+    /// Pin went from high to low, latched until acknowledged
+    pub const EDGE_FALL: Events = Events(1 << 2);
+    /// Pin went from low to high, latched until acknowledged
+    pub const EDGE_RISE: Events = Events(1 << 3);
+    /// Both edges
+    pub const EDGES: Events = Events(Self::EDGE_FALL.0 | Self::EDGE_RISE.0);
+
+    /// Events from their raw bits, as the hardware packs them per pin
+    ///
+    /// `bits`: the pin's 4 bits, the others are ignored
+    pub const fn from_bits(bits: u32) -> Events {
+        Events(bits & 0xf)
+    }
+
+    /// The raw event bits
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Whether every event of `other` is in `self`
+    ///
+    /// `other`: the events to look for
+    pub const fn contains(self, other: Events) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether there is no event
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for Events {
+    type Output = Events;
+
+    fn bitor(self, rhs: Events) -> Events {
+        Events(self.0 | rhs.0)
+    }
+}
+
+/// The two processors, each with its own interrupt enables and NVIC
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Core {
+    Proc0,
+    Proc1,
+}
+
+impl Core {
+    /// The core running this code
+    pub fn current() -> Core {
+        match SIO.cpuid().read().bits() {
+            0 => Core::Proc0,
+            _ => Core::Proc1,
+        }
+    }
+}
+
+/// Called from the dispatcher with the pin and the events that fired
+pub type GpioCallback = fn(pin: usize, events: Events);
+
+/// Registered callbacks as function addresses, 0 when there is none
+static CALLBACKS: [AtomicUsize; PIN_COUNT] = [const { AtomicUsize::new(0) }; PIN_COUNT];
+
+/// Interrupt register holding `pin`'s events and the events' position in it
+const fn locate(pin: usize) -> (usize, u32) {
+    (pin / 8, 4 * (pin % 8) as u32)
+}
+
+/// Registers the callback the dispatcher calls for a pin, replacing the previous one
+///
+/// `pin`: the GPIO number
+/// `callback`: the handler
+pub fn set_callback(pin: usize, callback: GpioCallback) {
+    if let Some(slot) = CALLBACKS.get(pin) {
+        slot.store(callback as usize, Ordering::Release);
+    }
+}
+
+/// Removes the callback of a pin, its events are still acknowledged
+///
+/// `pin`: the GPIO number
+pub fn clear_callback(pin: usize) {
+    if let Some(slot) = CALLBACKS.get(pin) {
+        slot.store(0, Ordering::Release);
+    }
+}
+
+/// Enables events of a pin on a core, stale edges are acknowledged first
+///
+/// `pin`: the GPIO number
+/// `events`: the events to enable
+/// `core`: the core to interrupt
+pub fn enable(pin: usize, events: Events, core: Core) {
+    if pin >= PIN_COUNT {
+        return;
+    }
+    acknowledge(pin, events);
+    let (reg, shift) = locate(pin);
+    let mask = events.bits() << shift;
+    match core {
+        Core::Proc0 => IO_BANK0.proc0_inte(reg).set_bits(|w| w.bits(mask)),
+        Core::Proc1 => IO_BANK0.proc1_inte(reg).set_bits(|w| w.bits(mask)),
+    };
+}
+
+/// Disables events of a pin on a core
+///
+/// `pin`: the GPIO number
+/// `events`: the events to disable
+/// `core`: the core to stop interrupting
+pub fn disable(pin: usize, events: Events, core: Core) {
+    if pin >= PIN_COUNT {
+        return;
+    }
+    let (reg, shift) = locate(pin);
+    let mask = events.bits() << shift;
+    match core {
+        Core::Proc0 => IO_BANK0.proc0_inte(reg).clear_bits(|w| w.bits(mask)),
+        Core::Proc1 => IO_BANK0.proc1_inte(reg).clear_bits(|w| w.bits(mask)),
+    };
+}
+
+/// Clears latched edges of a pin, levels clear themselves once the level changes
+///
+/// `pin`: the GPIO number
+/// `events`: the events to acknowledge
+pub fn acknowledge(pin: usize, events: Events) {
+    let (reg, shift) = locate(pin);
+    let edges = events.bits() & Events::EDGES.bits();
+    if pin < PIN_COUNT && edges != 0 {
+        IO_BANK0.intr(reg).write(|w| w.bits(edges << shift));
+    }
+}
+
+/// Events of a pin currently interrupting a core
+///
+/// `pin`: the GPIO number
+/// `core`: the interrupted core
+pub fn pending(pin: usize, core: Core) -> Events {
+    if pin >= PIN_COUNT {
+        return Events::NONE;
+    }
+    let (reg, shift) = locate(pin);
+    let ints = match core {
+        Core::Proc0 => IO_BANK0.proc0_ints(reg).read().bits(),
+        Core::Proc1 => IO_BANK0.proc1_ints(reg).read().bits(),
+    };
+    Events((ints >> shift) & 0xf)
+}
+
+/// Installs the dispatcher on IO_IRQ_BANK0 and enables it on the calling core
+///
+/// Call it from each core whose events should be handled.
+///
+/// # Safety
+///
+/// This function edits the vector table in ram, see [`set_irq_handler`]
+pub unsafe fn install() {
+    set_irq_handler(Interrupt::IO_IRQ_BANK0, dispatch);
+    nvic_enable(Interrupt::IO_IRQ_BANK0);
+}
+
+/// IO_IRQ_BANK0 handler: acknowledges the edges and runs the callbacks of the calling core
+fn dispatch() {
+    let core = Core::current();
+    for reg in 0..PIN_COUNT.div_ceil(8) {
+        let ints = match core {
+            Core::Proc0 => IO_BANK0.proc0_ints(reg).read().bits(),
+            Core::Proc1 => IO_BANK0.proc1_ints(reg).read().bits(),
+        };
+        if ints == 0 {
+            continue;
+        }
+        // Acknowledge before calling back, so an edge during the callback isn't lost
+        let edges = ints & (Events::EDGES.bits() * 0x1111_1111);
+        if edges != 0 {
+            IO_BANK0.intr(reg).write(|w| w.bits(edges));
+        }
+
+        for slot in 0..8 {
+            let events = Events((ints >> (4 * slot)) & 0xf);
+            if events.is_empty() {
+                continue;
+            }
+            let pin = reg * 8 + slot;
+            // The last register has slots past the package's pins
+            let raw = CALLBACKS.get(pin).map_or(0, |slot| slot.load(Ordering::Acquire));
+            if raw != 0 {
+                // SAFETY: a non-zero address is always a `GpioCallback` stored by `set_callback`
+                let callback = unsafe { core::mem::transmute::<usize, GpioCallback>(raw) };
+                callback(pin, events);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{acknowledge, disable, dispatch, enable, pending, set_callback, Core, Events, PIN_COUNT};
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use crate::backend::sim;
+    use crate::pac::IO_BANK0;
+
+    #[test]
+    fn test_enable_per_core() {
+        sim::reset();
+        enable(9, Events::EDGE_RISE | Events::LEVEL_LOW, Core::Proc0);
+        enable(9, Events::EDGE_FALL, Core::Proc1);
+        assert_eq!(IO_BANK0.proc0_inte(1).read().bits(), 0x90);
+        assert_eq!(IO_BANK0.proc1_inte(1).read().bits(), 0x40);
+        disable(9, Events::LEVEL_LOW, Core::Proc0);
+        assert_eq!(IO_BANK0.proc0_inte(1).read().bits(), 0x80);
+    }
+
+    #[test]
+    fn test_acknowledge_clears_edges_only() {
+        sim::reset();
+        sim::poke(IO_BANK0.intr(0).addr(), 0xf0);
+        acknowledge(1, Events::EDGES | Events::LEVEL_HIGH);
+        assert_eq!(IO_BANK0.intr(0).read().bits(), 0x30);
+    }
+
+    #[test]
+    fn test_dispatch_calls_back_and_acknowledges() {
+        // Pin and events of the last call, packed as pin << 4 | events
+        static SEEN: AtomicU32 = AtomicU32::new(0);
+        fn on_edge(pin: usize, events: Events) {
+            SEEN.store((pin as u32) << 4 | events.bits(), Ordering::Relaxed);
+        }
+
+        sim::reset();
+        set_callback(26, on_edge);
+        // Pin 26 is slot 2 of the fourth register, which only has 6 pins on the RP2350A
+        sim::poke(IO_BANK0.intr(3).addr(), 0x800);
+        sim::poke(IO_BANK0.proc0_ints(3).addr(), 0x800);
+        assert_eq!(pending(26, Core::Proc0), Events::EDGE_RISE);
+
+        dispatch();
+        assert_eq!(SEEN.load(Ordering::Relaxed), 26 << 4 | Events::EDGE_RISE.bits());
+        assert_eq!(IO_BANK0.intr(3).read().bits(), 0);
+    }
+
+    #[test]
+    fn test_pins_past_the_package_are_ignored() {
+        fn never(_pin: usize, _events: Events) {
+            panic!("called back for a missing pin");
+        }
+
+        sim::reset();
+        set_callback(PIN_COUNT, never);
+        enable(PIN_COUNT, Events::EDGES, Core::Proc0);
+        assert_eq!(pending(PIN_COUNT, Core::Proc0), Events::NONE);
+        // A stray bit in the last register's unused slots, the RP2350B's 48 pins leave none
+        if !PIN_COUNT.is_multiple_of(8) {
+            let stray = PIN_COUNT.div_ceil(8) * 8 - 1;
+            assert!(stray >= PIN_COUNT);
+            let (reg, shift) = super::locate(stray);
+            sim::poke(IO_BANK0.proc0_ints(reg).addr(), Events::EDGE_RISE.bits() << shift);
+            dispatch();
+        }
+    }
+}
@@ -1,70 +1,345 @@
 /// GPIO module
-pub mod regs;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU32, Ordering};
 
-use crate::gpio::regs::*;
-use crate::{bit, reg_read, reg_write, Valid, ATOMIC_CLEAR, ATOMIC_SET, RESETS_RESET, RESETS_RESET_DONE};
+use crate::pac::IO_BANK0;
+use crate::resets::{self, Reset, ResetHandle};
+use crate::Valid;
 
-// -------- helpers ----------
-/// GPIO control register offset
+pub mod any;
+pub mod irq;
+pub mod mode;
+pub mod pad;
+pub mod qspi;
+pub mod sio;
+
+use mode::{
+    Analog, BusKeep, Disabled, Floating, Function, FunctionKind, Input, OpenDrain, Output,
+    OutputType, PinMode, PullDown, PullUp, PushPull, ValidFunction, ValidMode,
+};
+use irq::{Core, Events, GpioCallback};
+use pad::Pad;
+use sio::SioBank;
+
+/// Number of GPIOs in bank 0, 30 on the RP2350A and 48 on the RP2350B
+#[cfg(not(feature = "rp2350b"))]
+pub const PIN_COUNT: usize = 30;
+/// Number of GPIOs in bank 0, 30 on the RP2350A and 48 on the RP2350B
+#[cfg(feature = "rp2350b")]
+pub const PIN_COUNT: usize = 48;
+
+/// Peripheral functions selected by a GPIO's FUNCSEL field
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Funcsel {
+    Hstx = 0,
+    Spi = 1,
+    Uart = 2,
+    I2c = 3,
+    Pwm = 4,
+    Sio = 5,
+    Pio0 = 6,
+    Pio1 = 7,
+    Pio2 = 8,
+    Gpck = 9,
+    Usb = 10,
+    UartAux = 11,
+    Null = 0x1f,
+}
+
+impl Funcsel {
+    /// Whether GPIO `pin` of bank 0 supports the function, the runtime twin of
+    /// [`mode::ValidFunction`]
+    ///
+    /// F9 is checked against its clock pins and QMI chip selects together.
+    ///
+    /// `pin`: the GPIO number
+    pub const fn available_on(self, pin: usize) -> bool {
+        if pin >= PIN_COUNT {
+            return false;
+        }
+        match self {
+            Funcsel::Hstx => matches!(pin, 12..=19),
+            Funcsel::Gpck => matches!(pin, 0 | 8 | 12..=15 | 19..=25 | 47),
+            Funcsel::UartAux => pin % 4 >= 2,
+            _ => true,
+        }
+    }
+}
+
+/// Pad resistors holding an undriven input at a known level
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pull {
+    None,
+    Up,
+    Down,
+    /// Both resistors, weakly holding the last level driven on the pin
+    BusKeep,
+}
+
+/// GPIOs currently owned, one bit per pin
+static TAKEN: [AtomicU32; 2] = [const { AtomicU32::new(0) }; 2];
+
+/// Whether a GPIO is owned by a pin, never for GPIOs the package doesn't have
 ///
 /// `pin`: the GPIO number
-#[inline(always)]
-pub const fn gpio_ctrl_offset(pin: usize) -> usize { 0x4 + pin * 0x8 }
+pub fn is_taken(pin: usize) -> bool {
+    pin < PIN_COUNT && TAKEN[pin / 32].load(Ordering::Acquire) & (1 << (pin % 32)) != 0
+}
+
+/// Ownership of a GPIO, given back once dropped
+struct Claim {
+    pin: usize,
+    // Keep the IO bank and pads out of reset while the pin is in use
+    _io_bank: ResetHandle,
+    _pads: ResetHandle,
+}
+
+impl Claim {
+    /// Takes GPIO `pin`, unless someone already owns it
+    fn new(pin: usize) -> Option<Self> {
+        debug_assert!(pin < PIN_COUNT);
+        let bit = 1 << (pin % 32);
+        if TAKEN[pin / 32].fetch_or(bit, Ordering::AcqRel) & bit != 0 {
+            return None;
+        }
+        // Bring IO_BANK0 + PADS out of reset, unless another user already did
+        let io_bank = resets::acquire(Reset::IoBank0);
+        let pads = resets::acquire(Reset::PadsBank0);
+        Some(Self { pin, _io_bank: io_bank, _pads: pads })
+    }
+}
+
+impl Drop for Claim {
+    /// Gives the GPIO back, the pin keeps its configuration
+    fn drop(&mut self) {
+        TAKEN[self.pin / 32].fetch_and(!(1 << (self.pin % 32)), Ordering::AcqRel);
+    }
+}
 
-/// GPIO pad register offset
+/// GPIO `N` in mode `M`, the IO bank stays out of reset while any pin is alive
 ///
-/// `pin`: the GPIO number
-#[inline(always)]
-pub const fn gpio_pad_offset(pin: usize) -> usize { 0x4 + pin * 0x4 }
+/// The mode defaults to a push-pull output, see [`mode`] for the others. A GPIO has one owner
+/// at a time, it can be taken again once the pin is dropped.
+pub struct Pin<const N: usize, M = Output<PushPull>>
+where
+    Pin<N>: Valid,
+{
+    claim: Claim,
+    _mode: PhantomData<M>,
+}
+
+impl<const N: usize, M: PinMode> Pin<N, M>
+where
+    Pin<N>: ValidMode<M> {
+    /// Takes the pin and configures it for its mode
+    ///
+    /// `Pin::<25>::take()` gives a push-pull output, `Pin::<6, Input<PullUp>>::take()` an input.
+    /// Panics if the pin is already taken, see [`Pin::try_take`].
+    pub fn take() -> Self {
+        match Self::try_take() {
+            Some(pin) => pin,
+            None => panic!("GPIO{} is already taken", N),
+        }
+    }
 
-pub struct Pin<const N: usize>(core::marker::PhantomData<()>)
-where 
-    Pin<N>: Valid;
+    /// Takes the pin and configures it for its mode, `None` if it's already taken
+    pub fn try_take() -> Option<Self> {
+        let claim = Claim::new(N)?;
+        M::configure(N);
+        Some(Self { claim, _mode: PhantomData })
+    }
+}
 
-impl<const N: usize> Pin<N>
+impl<const N: usize> Pin<N, Disabled>
 where
-    Pin<N>: Valid {}
+    Pin<N>: Valid {
+    /// Takes the pin without touching its configuration
+    fn claim() -> Option<Self> {
+        Some(Self { claim: Claim::new(N)?, _mode: PhantomData })
+    }
+}
 
-impl<const N: usize> Pin<N>
+impl<const N: usize, M> Pin<N, M>
 where
     Pin<N>: Valid {
-    pub fn take() -> Self {
-        // Reset IO_BANK0 + PADS
-        reg_write(RESETS_RESET + ATOMIC_CLEAR, bit(6) | bit(9));
-        while (reg_read(RESETS_RESET_DONE) & (bit(6) | bit(9))) != (bit(6) | bit(9)) {}
+    /// Reconfigures the pin for another mode
+    pub fn into_mode<M2: PinMode>(self) -> Pin<N, M2>
+    where
+        Pin<N>: ValidMode<M2>,
+    {
+        M2::configure(N);
+        Pin { claim: self.claim, _mode: PhantomData }
+    }
+
+    /// Reconfigures the pin as an input with a pull-up
+    pub fn into_pull_up_input(self) -> Pin<N, Input<PullUp>> {
+        self.into_mode()
+    }
+
+    /// Reconfigures the pin as an input with a pull-down
+    pub fn into_pull_down_input(self) -> Pin<N, Input<PullDown>> {
+        self.into_mode()
+    }
+
+    /// Reconfigures the pin as an input without pull resistors
+    pub fn into_floating_input(self) -> Pin<N, Input<Floating>> {
+        self.into_mode()
+    }
 
-        // Configure GPIO_N for SIO (funcsel = 5)
-        reg_write(IO_BANK0_BASE + ((0x08 * N) + 0x04) + ATOMIC_CLEAR, 0x1f);
-        reg_write(IO_BANK0_BASE + ((0x08 * N) + 0x04) + ATOMIC_SET, 0x05);
+    /// Reconfigures the pin as an input holding the last level driven on it
+    pub fn into_bus_keep_input(self) -> Pin<N, Input<BusKeep>> {
+        self.into_mode()
+    }
+
+    /// Reconfigures the pin as a push-pull output
+    pub fn into_push_pull_output(self) -> Pin<N, Output<PushPull>> {
+        self.into_mode()
+    }
+
+    /// Reconfigures the pin as an open-drain output, released (high) until cleared
+    pub fn into_open_drain_output(self) -> Pin<N, Output<OpenDrain>> {
+        self.into_mode()
+    }
+
+    /// Routes the pin to a peripheral function, which must be one the pin supports
+    pub fn into_function<F: FunctionKind>(self) -> Pin<N, Function<F>>
+    where
+        Pin<N>: ValidFunction<F>,
+    {
+        self.into_mode()
+    }
 
-        // Enable GPIO25 output
-        reg_write(SIO_GPIO_OE_SET, bit(N));
+    /// Reconfigures the pin as an analog input, digital input and output off
+    pub fn into_analog(self) -> Pin<N, Analog> {
+        self.into_mode()
+    }
+
+    /// Disconnects the pin
+    pub fn into_disabled(self) -> Pin<N, Disabled> {
+        self.into_mode()
+    }
+
+    /// The pin's pad, for its electrical settings
+    pub fn pad(&self) -> Pad {
+        Pad::new(N)
+    }
+
+    /// Enables the pad's input buffer, [`Pin::value`] reads 0 while it's disabled
+    ///
+    /// `enable`: true to enable the input
+    pub fn set_input_enable(&self, enable: bool) {
+        self.pad().set_input_enable(enable);
+    }
+
+    /// Enables the Schmitt trigger on the input, filtering noise on slow edges
+    ///
+    /// `enable`: true to enable hysteresis
+    pub fn set_schmitt(&self, enable: bool) {
+        self.pad().set_schmitt(enable);
+    }
+
+    /// The pad resistors
+    pub fn pull(&self) -> Pull {
+        self.pad().pull()
+    }
+
+    /// Level on the pin, 1 for high and 0 for low
+    ///
+    /// Outputs read back the level they drive.
+    pub fn value(&self) -> u32 {
+        (SioBank::of(N).input() >> (N % 32)) & 1
+    }
+
+    /// Whether the pin is high
+    pub fn is_high(&self) -> bool {
+        self.value() != 0
+    }
 
-        // Clear input disable + pull up/down
-        reg_write(
-            PADS_BANK0_BASE + ((0x04 * N) + 0x04) + ATOMIC_CLEAR,
-            bit(7) | bit(8),
-        );
-        Self(core::marker::PhantomData)
+    /// Whether the pin is low
+    pub fn is_low(&self) -> bool {
+        self.value() == 0
     }
 
+    /// Interrupts the calling core on `events` and runs `callback` from the dispatcher
+    ///
+    /// The dispatcher must be installed, see [`irq::install`].
+    ///
+    /// `events`: the events to enable
+    /// `callback`: the handler
+    pub fn enable_interrupt(&self, events: Events, callback: GpioCallback) {
+        irq::set_callback(N, callback);
+        irq::enable(N, events, Core::current());
+    }
+
+    /// Stops interrupting the calling core on `events`
+    ///
+    /// `events`: the events to disable
+    pub fn disable_interrupt(&self, events: Events) {
+        irq::disable(N, events, Core::current());
+    }
+}
+
+impl<const N: usize, T: OutputType> Pin<N, Output<T>>
+where
+    Pin<N>: Valid {
     /// Set the pin high
     pub fn set(&self) {
-        reg_write(SIO_GPIO_OUT_SET, bit(N));
+        T::set(SioBank::of(N), SioBank::mask(N));
     }
 
     /// Set the pin low
     pub fn clear(&self) {
-        reg_write(SIO_GPIO_OUT_CLR, bit(N));
+        T::clear(SioBank::of(N), SioBank::mask(N));
     }
 
     /// Toggle the pin
     pub fn toggle(&self) {
-        reg_write(SIO_GPIO_OUT_XOR, bit(N));
+        T::toggle(SioBank::of(N), SioBank::mask(N));
+    }
+}
+
+/// GPIO handed to a peripheral function, it goes back to the NULL function once dropped
+pub struct FunctionPin {
+    claim: Claim,
+}
+
+impl FunctionPin {
+    /// Takes a GPIO and routes it to a peripheral function
+    ///
+    /// Returns `None` if the GPIO is already taken, doesn't exist on the package or doesn't
+    /// support the function, see [`Funcsel::available_on`].
+    ///
+    /// `pin`: the GPIO number
+    /// `funcsel`: the function
+    pub fn claim(pin: usize, funcsel: Funcsel) -> Option<Self> {
+        if !funcsel.available_on(pin) {
+            return None;
+        }
+        let claim = Claim::new(pin)?;
+
+        IO_BANK0.gpio_ctrl(pin).modify(|_, w| w.funcsel().bits(funcsel as u32));
+        let pad = Pad::new(pin);
+        pad.set_output_disable(false);
+        pad.set_isolated(false);
+        Some(Self { claim })
     }
 
-    pub fn value(&self) -> u32 {
-        todo!("Not implemented!");
+    /// The GPIO number
+    pub fn pin(&self) -> usize {
+        self.claim.pin
+    }
+
+    /// The pin's pad, for its electrical settings
+    pub fn pad(&self) -> Pad {
+        Pad::new(self.claim.pin)
+    }
+}
+
+impl Drop for FunctionPin {
+    /// Disconnects the GPIO from the peripheral
+    fn drop(&mut self) {
+        IO_BANK0.gpio_ctrl(self.claim.pin).modify(|_, w| w.funcsel().bits(Funcsel::Null as u32));
     }
 }
 
@@ -80,7 +355,285 @@ impl_pin_valid!(
     0, 1, 2, 3, 4, 5, 6, 7,
     8, 9, 10, 11, 12, 13, 14, 15,
     16, 17, 18, 19, 20, 21, 22, 23,
-    24, 25, 26, 27, 28, 29, 30, 31,
-    32, 33, 34, 35, 36, 37, 38, 39,
-    40, 41, 42, 43, 44, 45, 46, 47
-);
\ No newline at end of file
+    24, 25, 26, 27, 28, 29
+);
+
+// GPIO30 to 47 are only bonded out on the RP2350B
+#[cfg(feature = "rp2350b")]
+impl_pin_valid!(
+    30, 31, 32, 33, 34, 35, 36, 37,
+    38, 39, 40, 41, 42, 43, 44, 45,
+    46, 47
+);
+
+macro_rules! pins {
+    (
+        console: $($cfield:ident: $cn:expr),*;
+        $($(#[$attr:meta])* $field:ident: $n:expr),* $(,)?
+    ) => {
+        /// Every GPIO of bank 0, see [`Peripherals`](crate::Peripherals)
+        ///
+        /// The pins keep the configuration they had, `Disabled` only means nobody configured
+        /// them through this struct: convert them to the mode they're needed in. GPIO0 and GPIO1
+        /// are `None` while someone else holds them, the UART0 console keeps them for good once
+        /// [`uart_init`](crate::uart::uart_init) ran.
+        pub struct Pins {
+            $(pub $cfield: Option<Pin<$cn, Disabled>>,)*
+            $($(#[$attr])* pub $field: Pin<$n, Disabled>,)*
+        }
+
+        impl Pins {
+            /// Takes every pin, `None` if any of them but the console's is already taken
+            pub(crate) fn take() -> Option<Self> {
+                Some(Self {
+                    $($cfield: Pin::claim(),)*
+                    $($(#[$attr])* $field: Pin::claim()?,)*
+                })
+            }
+        }
+    };
+}
+
+pins!(
+    console: gpio0: 0, gpio1: 1;
+    gpio2: 2, gpio3: 3, gpio4: 4, gpio5: 5, gpio6: 6, gpio7: 7,
+    gpio8: 8, gpio9: 9, gpio10: 10, gpio11: 11, gpio12: 12, gpio13: 13, gpio14: 14, gpio15: 15,
+    gpio16: 16, gpio17: 17, gpio18: 18, gpio19: 19, gpio20: 20, gpio21: 21, gpio22: 22, gpio23: 23,
+    gpio24: 24, gpio25: 25, gpio26: 26, gpio27: 27, gpio28: 28, gpio29: 29,
+    #[cfg(feature = "rp2350b")] gpio30: 30,
+    #[cfg(feature = "rp2350b")] gpio31: 31,
+    #[cfg(feature = "rp2350b")] gpio32: 32,
+    #[cfg(feature = "rp2350b")] gpio33: 33,
+    #[cfg(feature = "rp2350b")] gpio34: 34,
+    #[cfg(feature = "rp2350b")] gpio35: 35,
+    #[cfg(feature = "rp2350b")] gpio36: 36,
+    #[cfg(feature = "rp2350b")] gpio37: 37,
+    #[cfg(feature = "rp2350b")] gpio38: 38,
+    #[cfg(feature = "rp2350b")] gpio39: 39,
+    #[cfg(feature = "rp2350b")] gpio40: 40,
+    #[cfg(feature = "rp2350b")] gpio41: 41,
+    #[cfg(feature = "rp2350b")] gpio42: 42,
+    #[cfg(feature = "rp2350b")] gpio43: 43,
+    #[cfg(feature = "rp2350b")] gpio44: 44,
+    #[cfg(feature = "rp2350b")] gpio45: 45,
+    #[cfg(feature = "rp2350b")] gpio46: 46,
+    #[cfg(feature = "rp2350b")] gpio47: 47,
+);
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::mode::{Function, Gpck, Hstx, Input, PullUp, Qmi, Uart, UartAux};
+    use super::any::AnyPin;
+    use super::{is_taken, FunctionPin, Funcsel, Pin, Pull, PIN_COUNT};
+    use crate::backend::sim;
+    use crate::Peripherals;
+    use crate::pac::{IO_BANK0, PADS_BANK0, RESETS, SIO};
+    use crate::resets::{is_in_reset, Reset};
+
+    #[test]
+    fn test_take_configures_sio_output() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        let _led = Pin::<25>::take();
+        assert_eq!(IO_BANK0.gpio_ctrl(25).read().funcsel().bits(), Funcsel::Sio as u32);
+        assert_eq!(SIO.gpio_oe().read().bits(), 1 << 25);
+        assert!(!PADS_BANK0.gpio(25).read().iso());
+    }
+
+    #[test]
+    fn test_take_input_with_pull() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        SIO.gpio_oe().write(|w| w.bits(1 << 6));
+        let button = Pin::<6, Input<PullUp>>::take();
+        assert_eq!(SIO.gpio_oe().read().bits(), 0);
+        let pad = PADS_BANK0.gpio(6).read();
+        assert!(pad.ie() && pad.pue() && !pad.pde() && !pad.iso());
+        assert_eq!(button.pull(), Pull::Up);
+
+        let button = button.into_bus_keep_input();
+        assert_eq!(button.pull(), Pull::BusKeep);
+        button.set_schmitt(true);
+        assert!(PADS_BANK0.gpio(6).read().schmitt());
+    }
+
+    #[test]
+    fn test_value_reads_gpio_in() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        let button = Pin::<7>::take().into_floating_input();
+        sim::poke(SIO.gpio_in().addr(), 1 << 7);
+        assert_eq!(button.value(), 1);
+        assert!(button.is_high());
+        sim::poke(SIO.gpio_in().addr(), !(1 << 7));
+        assert!(button.is_low());
+    }
+
+    #[test]
+    fn test_set_clear_toggle() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        let led = Pin::<3>::take();
+        led.set();
+        assert_eq!(SIO.gpio_out().read().bits(), 1 << 3);
+        led.toggle();
+        assert_eq!(SIO.gpio_out().read().bits(), 0);
+        led.toggle();
+        led.clear();
+        assert_eq!(SIO.gpio_out().read().bits(), 0);
+    }
+
+    #[test]
+    fn test_open_drain_switches_output_enable() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        SIO.gpio_out().write(|w| w.bits(1 << 8));
+        let line = Pin::<8>::take().into_open_drain_output();
+        // Released, and low whenever it drives
+        assert_eq!(SIO.gpio_oe().read().bits(), 0);
+        assert_eq!(SIO.gpio_out().read().bits(), 0);
+        line.clear();
+        assert_eq!(SIO.gpio_oe().read().bits(), 1 << 8);
+        line.set();
+        assert_eq!(SIO.gpio_oe().read().bits(), 0);
+        line.toggle();
+        assert_eq!(SIO.gpio_oe().read().bits(), 1 << 8);
+    }
+
+    #[test]
+    fn test_function_analog_and_disabled_modes() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        let tx = Pin::<8>::take().into_function::<Uart>();
+        assert_eq!(IO_BANK0.gpio_ctrl(8).read().funcsel().bits(), Funcsel::Uart as u32);
+
+        let adc = Pin::<26>::take().into_analog();
+        let pad = PADS_BANK0.gpio(26).read();
+        assert!(pad.od() && !pad.ie() && !pad.pue() && !pad.pde());
+        assert_eq!(SIO.gpio_oe().read().bits() & 1 << 26, 0);
+        assert_eq!(IO_BANK0.gpio_ctrl(26).read().funcsel().bits(), Funcsel::Null as u32);
+
+        let _off = tx.into_disabled();
+        assert_eq!(IO_BANK0.gpio_ctrl(8).read().funcsel().bits(), Funcsel::Null as u32);
+        let _led = adc.into_push_pull_output();
+        assert!(!PADS_BANK0.gpio(26).read().od());
+        assert_eq!(SIO.gpio_oe().read().bits() & 1 << 26, 1 << 26);
+    }
+
+    #[test]
+    fn test_restricted_functions() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        let _clock = Pin::<21, Function<Gpck>>::take();
+        let _hstx = Pin::<16, Function<Hstx>>::take();
+        let _cs1 = Pin::<19, Function<Qmi>>::take();
+        let _tx = Pin::<6>::take().into_function::<UartAux>();
+        assert_eq!(IO_BANK0.gpio_ctrl(21).read().funcsel().bits(), Funcsel::Gpck as u32);
+        assert_eq!(IO_BANK0.gpio_ctrl(16).read().funcsel().bits(), Funcsel::Hstx as u32);
+        assert_eq!(IO_BANK0.gpio_ctrl(19).read().funcsel().bits(), Funcsel::Gpck as u32);
+        assert_eq!(IO_BANK0.gpio_ctrl(6).read().funcsel().bits(), Funcsel::UartAux as u32);
+    }
+
+    #[test]
+    fn test_available_on() {
+        assert!(Funcsel::Hstx.available_on(12) && !Funcsel::Hstx.available_on(20));
+        assert!(Funcsel::Gpck.available_on(25) && !Funcsel::Gpck.available_on(26));
+        assert!(Funcsel::UartAux.available_on(3) && !Funcsel::UartAux.available_on(4));
+        assert!(Funcsel::Pio2.available_on(PIN_COUNT - 1) && !Funcsel::Pio2.available_on(PIN_COUNT));
+    }
+
+    #[test]
+    fn test_second_pin_keeps_bank_out_of_reset() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        let first = Pin::<4>::take();
+        // Putting the bank back into reset would glitch every pin already in use
+        sim::on_write(RESETS.reset().addr(), |addr, val| {
+            let bank = (1 << Reset::IoBank0 as u32) | (1 << Reset::PadsBank0 as u32);
+            assert_eq!(val & !sim::peek(addr) & bank, 0, "IO bank reset while a pin is in use");
+            sim::poke(addr, val);
+        });
+        let second = Pin::<5>::take();
+        drop(first);
+        assert!(!is_in_reset(Reset::IoBank0));
+        assert!(!is_in_reset(Reset::PadsBank0));
+
+        // The last pin may put the bank back into reset
+        sim::on_write(RESETS.reset().addr(), sim::poke);
+        drop(second);
+    }
+
+    #[test]
+    #[cfg(feature = "rp2350b")]
+    fn test_high_bank_pins() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        let led = Pin::<40>::take();
+        led.set();
+        assert_eq!(SIO.gpio_hi_oe().read().bits(), 1 << 8);
+        assert_eq!(SIO.gpio_hi_out().read().bits(), 1 << 8);
+        assert_eq!(SIO.gpio_out().read().bits() | SIO.gpio_oe().read().bits(), 0);
+        led.toggle();
+        assert_eq!(SIO.gpio_hi_out().read().bits(), 0);
+
+        let button = Pin::<47>::take().into_floating_input();
+        sim::poke(SIO.gpio_hi_in().addr(), 1 << 15);
+        assert!(button.is_high());
+
+        // One SIO write can't reach both halves
+        let mixed = super::any::PinGroup::new([AnyPin::from(led), Pin::<31>::take().into()]);
+        assert!(mixed.is_err());
+    }
+
+    #[test]
+    fn test_pin_taken_once() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        let led = Pin::<12>::take();
+        assert!(is_taken(12));
+        assert!(Pin::<12, Input<PullUp>>::try_take().is_none());
+        assert!(FunctionPin::claim(12, Funcsel::Pwm).is_none());
+        assert!(FunctionPin::claim(20, Funcsel::Hstx).is_none());
+        assert!(FunctionPin::claim(PIN_COUNT, Funcsel::Pwm).is_none());
+        assert!(!is_taken(20));
+        assert!(!is_taken(PIN_COUNT) && !is_taken(64));
+        // The failed attempts left the pin alone
+        assert_eq!(SIO.gpio_oe().read().bits(), 1 << 12);
+
+        let any: AnyPin = led.into_floating_input().into_push_pull_output().into();
+        assert!(is_taken(12));
+        drop(any);
+        assert!(!is_taken(12));
+        assert!(Pin::<12>::try_take().is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "GPIO13 is already taken")]
+    fn test_double_take_panics() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        let _first = Pin::<13>::take();
+        let _second = Pin::<13>::take();
+    }
+
+    #[test]
+    fn test_peripherals_taken_once() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        IO_BANK0.gpio_ctrl(0).write(|w| w.funcsel().bits(Funcsel::Uart as u32));
+        // A pin in use holds the peripherals back, until it's dropped
+        let button = Pin::<5, Input<PullUp>>::take();
+        assert!(Peripherals::take().is_none());
+        assert!(!is_taken(4));
+        drop(button);
+        let peripherals = Peripherals::take().unwrap();
+        assert!((0..PIN_COUNT).all(is_taken));
+        assert!(Peripherals::take().is_none());
+        // Taking the pins doesn't touch them, the console keeps its UART
+        assert_eq!(IO_BANK0.gpio_ctrl(0).read().funcsel().bits(), Funcsel::Uart as u32);
+
+        let led = peripherals.pins.gpio2.into_push_pull_output();
+        led.set();
+        assert_eq!(SIO.gpio_out().read().bits(), 1 << 2);
+    }
+}
@@ -0,0 +1,291 @@
+//! Pin modes
+//!
+//! A [`Pin`](crate::gpio::Pin) carries its mode in its type, so only the operations that make
+//! sense for it compile: an `Output<PushPull>` can be set and cleared, an `Input<PullUp>` read,
+//! a `Function<Uart>` only handed to the UART driver.
+//!
+//! Take a pin straight in the mode it's needed in, [`Pin::take`](crate::gpio::Pin::take) with
+//! the default mode drives the pin until it's converted.
+//!
+//! ```ignore
+//! let button = Pin::<6, Input<PullUp>>::take();
+//! let tx = Pin::<4, Function<Uart>>::take();
+//! let led = button.into_push_pull_output();
+//! ```
+
+use core::marker::PhantomData;
+
+use crate::gpio::pad::Pad;
+use crate::gpio::sio::SioBank;
+use crate::gpio::{Funcsel, Pin, Pull};
+use crate::pac::IO_BANK0;
+use crate::Valid;
+
+/// A mode a pin can be put in
+pub trait PinMode {
+    /// Function the mode selects, SIO for inputs and outputs, NULL when disconnected
+    const FUNCSEL: Funcsel;
+
+    /// Configures GPIO `pin` for the mode
+    fn configure(pin: usize);
+}
+
+/// Implemented by the pins that support mode `M`
+pub trait ValidMode<M>: Valid {}
+
+/// Implemented by the pins that can be routed to the function `F`
+pub trait ValidFunction<F>: Valid {}
+
+/// SIO input, `P` selects the pad resistors
+pub struct Input<P>(PhantomData<P>);
+
+/// SIO output, `T` selects how it's driven
+pub struct Output<T>(PhantomData<T>);
+
+/// Routed to the peripheral function `F`
+pub struct Function<F>(PhantomData<F>);
+
+/// Digital input and output off, for the ADC inputs
+pub struct Analog;
+
+/// Not connected to anything, the state of a pin after reset
+pub struct Disabled;
+
+/// Pad resistors of an [`Input`]
+pub trait PullType {
+    const PULL: Pull;
+}
+
+/// Pulled high
+pub struct PullUp;
+/// Pulled low
+pub struct PullDown;
+/// No resistor, the level is undefined unless something drives the pin
+pub struct Floating;
+/// Holds the last level driven on the pin
+pub struct BusKeep;
+
+impl PullType for PullUp {
+    const PULL: Pull = Pull::Up;
+}
+
+impl PullType for PullDown {
+    const PULL: Pull = Pull::Down;
+}
+
+impl PullType for Floating {
+    const PULL: Pull = Pull::None;
+}
+
+impl PullType for BusKeep {
+    const PULL: Pull = Pull::BusKeep;
+}
+
+/// How an [`Output`] drives the pin, `mask` holding the pins' bits in `bank`
+pub trait OutputType {
+    /// Configures the SIO side of an output on the pins in `mask`
+    fn configure(bank: SioBank, mask: u32);
+    /// Drives the pins in `mask` high
+    fn set(bank: SioBank, mask: u32);
+    /// Drives the pins in `mask` low
+    fn clear(bank: SioBank, mask: u32);
+    /// Inverts the pins in `mask`
+    fn toggle(bank: SioBank, mask: u32);
+    /// Pins currently set high (or released, for open drain)
+    fn state(bank: SioBank) -> u32;
+}
+
+/// Drives both levels
+pub struct PushPull;
+
+/// Only drives low, high is left to a pull-up: the output stays low and the output enable
+/// switches between driving it and releasing the pin
+pub struct OpenDrain;
+
+impl OutputType for PushPull {
+    fn configure(bank: SioBank, mask: u32) {
+        bank.set_output_enable(mask);
+    }
+
+    fn set(bank: SioBank, mask: u32) {
+        bank.set_output(mask);
+    }
+
+    fn clear(bank: SioBank, mask: u32) {
+        bank.clear_output(mask);
+    }
+
+    fn toggle(bank: SioBank, mask: u32) {
+        bank.toggle_output(mask);
+    }
+
+    fn state(bank: SioBank) -> u32 {
+        bank.output()
+    }
+}
+
+impl OutputType for OpenDrain {
+    fn configure(bank: SioBank, mask: u32) {
+        // Released until cleared
+        bank.clear_output(mask);
+        bank.clear_output_enable(mask);
+    }
+
+    fn set(bank: SioBank, mask: u32) {
+        bank.clear_output_enable(mask);
+    }
+
+    fn clear(bank: SioBank, mask: u32) {
+        bank.set_output_enable(mask);
+    }
+
+    fn toggle(bank: SioBank, mask: u32) {
+        bank.toggle_output_enable(mask);
+    }
+
+    fn state(bank: SioBank) -> u32 {
+        !bank.output_enable()
+    }
+}
+
+/// Peripheral functions a [`Function`] pin can be routed to
+pub trait FunctionKind {
+    const FUNCSEL: Funcsel;
+}
+
+macro_rules! function_kinds {
+    ($($(#[$doc:meta])* $name:ident => $funcsel:ident),* $(,)?) => {
+        $(
+            $(#[$doc])*
+            pub struct $name;
+
+            impl FunctionKind for $name {
+                const FUNCSEL: Funcsel = Funcsel::$funcsel;
+            }
+        )*
+    };
+}
+
+function_kinds!(
+    /// HSTX output
+    Hstx => Hstx,
+    /// SPI0 or SPI1
+    Spi => Spi,
+    /// UART0 or UART1 TX/RX/CTS/RTS
+    Uart => Uart,
+    /// I2C0 or I2C1
+    I2c => I2c,
+    /// PWM slice A/B
+    Pwm => Pwm,
+    /// PIO0 state machines
+    Pio0 => Pio0,
+    /// PIO1 state machines
+    Pio1 => Pio1,
+    /// PIO2 state machines
+    Pio2 => Pio2,
+    /// Clock input or output, see [`gpout`](crate::clocks::gpout)
+    Gpck => Gpck,
+    /// USB VBUS and overcurrent signals
+    Usb => Usb,
+    /// UART0 or UART1 TX/RX on the pins normally carrying CTS/RTS
+    UartAux => UartAux,
+    /// QSPI memory interface second chip select, shares F9 with [`Gpck`]
+    Qmi => Gpck,
+);
+
+/// Functions every GPIO supports
+pub trait EveryPin {}
+
+impl EveryPin for Spi {}
+impl EveryPin for Uart {}
+impl EveryPin for I2c {}
+impl EveryPin for Pwm {}
+impl EveryPin for Pio0 {}
+impl EveryPin for Pio1 {}
+impl EveryPin for Pio2 {}
+impl EveryPin for Usb {}
+
+impl<const N: usize, F: EveryPin> ValidFunction<F> for Pin<N> where Pin<N>: Valid {}
+
+macro_rules! impl_valid_function {
+    ($kind:ty: $($n:expr),*) => {
+        $(
+            impl ValidFunction<$kind> for Pin<$n> {}
+        )*
+    };
+}
+
+impl_valid_function!(Hstx: 12, 13, 14, 15, 16, 17, 18, 19);
+impl_valid_function!(Gpck: 12, 13, 14, 15, 20, 21, 22, 23, 24, 25);
+impl_valid_function!(Qmi: 0, 8, 19);
+impl_valid_function!(UartAux: 2, 3, 6, 7, 10, 11, 14, 15, 18, 19, 22, 23, 26, 27);
+
+#[cfg(feature = "rp2350b")]
+impl_valid_function!(Qmi: 47);
+#[cfg(feature = "rp2350b")]
+impl_valid_function!(UartAux: 30, 31, 34, 35, 38, 39, 42, 43, 46, 47);
+
+impl<const N: usize, P> ValidMode<Input<P>> for Pin<N> where Pin<N>: Valid {}
+impl<const N: usize, T> ValidMode<Output<T>> for Pin<N> where Pin<N>: Valid {}
+impl<const N: usize> ValidMode<Analog> for Pin<N> where Pin<N>: Valid {}
+impl<const N: usize> ValidMode<Disabled> for Pin<N> where Pin<N>: Valid {}
+impl<const N: usize, F> ValidMode<Function<F>> for Pin<N> where Pin<N>: ValidFunction<F> {}
+
+/// Routes GPIO `pin` to `funcsel` with a usable pad: output allowed, input on, then released
+fn route(pin: usize, funcsel: Funcsel) {
+    let pad = Pad::new(pin);
+    pad.set_output_disable(false);
+    pad.set_input_enable(true);
+    IO_BANK0.gpio_ctrl(pin).modify(|_, w| w.funcsel().bits(funcsel as u32));
+    pad.set_isolated(false);
+}
+
+impl<P: PullType> PinMode for Input<P> {
+    const FUNCSEL: Funcsel = Funcsel::Sio;
+
+    fn configure(pin: usize) {
+        SioBank::of(pin).clear_output_enable(SioBank::mask(pin));
+        Pad::new(pin).set_pull(P::PULL);
+        route(pin, Funcsel::Sio);
+    }
+}
+
+impl<T: OutputType> PinMode for Output<T> {
+    const FUNCSEL: Funcsel = Funcsel::Sio;
+
+    fn configure(pin: usize) {
+        T::configure(SioBank::of(pin), SioBank::mask(pin));
+        route(pin, Funcsel::Sio);
+    }
+}
+
+impl<F: FunctionKind> PinMode for Function<F> {
+    const FUNCSEL: Funcsel = F::FUNCSEL;
+
+    fn configure(pin: usize) {
+        route(pin, F::FUNCSEL);
+    }
+}
+
+impl PinMode for Analog {
+    const FUNCSEL: Funcsel = Funcsel::Null;
+
+    fn configure(pin: usize) {
+        IO_BANK0.gpio_ctrl(pin).modify(|_, w| w.funcsel().bits(Funcsel::Null as u32));
+        SioBank::of(pin).clear_output_enable(SioBank::mask(pin));
+        let pad = Pad::new(pin);
+        pad.set_pull(Pull::None);
+        pad.set_output_disable(true);
+        pad.set_input_enable(false);
+        pad.set_isolated(false);
+    }
+}
+
+impl PinMode for Disabled {
+    const FUNCSEL: Funcsel = Funcsel::Null;
+
+    fn configure(pin: usize) {
+        IO_BANK0.gpio_ctrl(pin).modify(|_, w| w.funcsel().bits(Funcsel::Null as u32));
+        SioBank::of(pin).clear_output_enable(SioBank::mask(pin));
+    }
+}
@@ -0,0 +1,262 @@
+//! Pad electrical configuration
+//!
+//! Every GPIO goes through a pad setting its drive strength, slew rate, input buffer, Schmitt
+//! trigger and pull resistors. Pads come out of reset isolated: they hold their state until
+//! software configured them and released the isolation latch, so a pad is configured first and
+//! released last.
+//!
+//! ```ignore
+//! let sck = Pin::<18, Function<Spi>>::take();
+//! sck.pad().configure(&PadConfig::new().drive(GpioDrive::_12ma).slew(SlewRate::Fast));
+//! ```
+
+use crate::gpio::Pull;
+use crate::pac::pads_bank0::GpioDrive;
+use crate::pac::PADS_BANK0;
+
+/// How fast the output edges are
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlewRate {
+    /// Less ringing and interference, enough for most signals
+    Slow,
+    /// For high-speed buses
+    Fast,
+}
+
+/// Electrical settings of a pad
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PadConfig {
+    pub drive: GpioDrive,
+    pub slew: SlewRate,
+    pub schmitt: bool,
+    pub input_enable: bool,
+    /// Disables the output whatever the selected function asks for
+    pub output_disable: bool,
+    pub pull: Pull,
+}
+
+impl PadConfig {
+    /// A usable digital pad: 4 mA, slow edges, Schmitt trigger and input on, no pulls
+    pub const fn new() -> Self {
+        Self {
+            drive: GpioDrive::_4ma,
+            slew: SlewRate::Slow,
+            schmitt: true,
+            input_enable: true,
+            output_disable: false,
+            pull: Pull::None,
+        }
+    }
+
+    /// Sets the drive strength
+    ///
+    /// `drive`: 2, 4, 8 or 12 mA
+    pub const fn drive(mut self, drive: GpioDrive) -> Self {
+        self.drive = drive;
+        self
+    }
+
+    /// Sets the slew rate
+    ///
+    /// `slew`: how fast the edges are
+    pub const fn slew(mut self, slew: SlewRate) -> Self {
+        self.slew = slew;
+        self
+    }
+
+    /// Enables or disables the Schmitt trigger
+    ///
+    /// `enable`: true for hysteresis on the input
+    pub const fn schmitt(mut self, enable: bool) -> Self {
+        self.schmitt = enable;
+        self
+    }
+
+    /// Enables or disables the input buffer
+    ///
+    /// `enable`: true to read the pin
+    pub const fn input_enable(mut self, enable: bool) -> Self {
+        self.input_enable = enable;
+        self
+    }
+
+    /// Enables or disables the output disable override
+    ///
+    /// `disable`: true to never drive the pin
+    pub const fn output_disable(mut self, disable: bool) -> Self {
+        self.output_disable = disable;
+        self
+    }
+
+    /// Selects the pull resistors
+    ///
+    /// `pull`: the resistors to enable
+    pub const fn pull(mut self, pull: Pull) -> Self {
+        self.pull = pull;
+        self
+    }
+}
+
+impl Default for PadConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The pad of a GPIO, obtained from the pin owning it
+#[derive(Debug)]
+pub struct Pad {
+    pin: usize,
+}
+
+impl Pad {
+    /// The pad of GPIO `pin`, only pin types hand these out
+    pub(crate) const fn new(pin: usize) -> Self {
+        Self { pin }
+    }
+
+    /// Applies a whole configuration, the isolation latch is left as it is
+    ///
+    /// `config`: the settings
+    pub fn configure(&self, config: &PadConfig) {
+        let (up, down) = pull_bits(config.pull);
+        PADS_BANK0.gpio(self.pin).modify(|_, w| {
+            w.drive().variant(config.drive)
+                .slewfast().bit(config.slew == SlewRate::Fast)
+                .schmitt().bit(config.schmitt)
+                .ie().bit(config.input_enable)
+                .od().bit(config.output_disable)
+                .pue().bit(up)
+                .pde().bit(down)
+        });
+    }
+
+    /// Reads the configuration back
+    pub fn config(&self) -> PadConfig {
+        let pad = PADS_BANK0.gpio(self.pin).read();
+        PadConfig {
+            drive: pad.drive().variant().unwrap_or(GpioDrive::_4ma),
+            slew: if pad.slewfast() { SlewRate::Fast } else { SlewRate::Slow },
+            schmitt: pad.schmitt(),
+            input_enable: pad.ie(),
+            output_disable: pad.od(),
+            pull: match (pad.pue(), pad.pde()) {
+                (false, false) => Pull::None,
+                (true, false) => Pull::Up,
+                (false, true) => Pull::Down,
+                (true, true) => Pull::BusKeep,
+            },
+        }
+    }
+
+    /// Sets the drive strength
+    ///
+    /// `drive`: 2, 4, 8 or 12 mA
+    pub fn set_drive(&self, drive: GpioDrive) {
+        PADS_BANK0.gpio(self.pin).modify(|_, w| w.drive().variant(drive));
+    }
+
+    /// Sets the slew rate
+    ///
+    /// `slew`: how fast the edges are
+    pub fn set_slew(&self, slew: SlewRate) {
+        PADS_BANK0.gpio(self.pin).modify(|_, w| w.slewfast().bit(slew == SlewRate::Fast));
+    }
+
+    /// Enables the Schmitt trigger on the input, filtering noise on slow edges
+    ///
+    /// `enable`: true to enable hysteresis
+    pub fn set_schmitt(&self, enable: bool) {
+        PADS_BANK0.gpio(self.pin).modify(|_, w| w.schmitt().bit(enable));
+    }
+
+    /// Enables the input buffer, the pin reads 0 while it's disabled
+    ///
+    /// `enable`: true to enable the input
+    pub fn set_input_enable(&self, enable: bool) {
+        PADS_BANK0.gpio(self.pin).modify(|_, w| w.ie().bit(enable));
+    }
+
+    /// Disables the output, overriding the selected function
+    ///
+    /// `disable`: true to never drive the pin
+    pub fn set_output_disable(&self, disable: bool) {
+        PADS_BANK0.gpio(self.pin).modify(|_, w| w.od().bit(disable));
+    }
+
+    /// Selects the pull resistors
+    ///
+    /// `pull`: the resistors to enable
+    pub fn set_pull(&self, pull: Pull) {
+        let (up, down) = pull_bits(pull);
+        PADS_BANK0.gpio(self.pin).modify(|_, w| w.pue().bit(up).pde().bit(down));
+    }
+
+    /// The pull resistors
+    pub fn pull(&self) -> Pull {
+        self.config().pull
+    }
+
+    /// Latches the pad in its current state, or releases it
+    ///
+    /// An isolated pad ignores its configuration and the peripherals, it keeps driving the level
+    /// it had. Release it once the pad and the function are configured.
+    ///
+    /// `isolate`: true to latch, false to release
+    pub fn set_isolated(&self, isolate: bool) {
+        PADS_BANK0.gpio(self.pin).modify(|_, w| w.iso().bit(isolate));
+    }
+
+    /// Whether the pad is latched
+    pub fn is_isolated(&self) -> bool {
+        PADS_BANK0.gpio(self.pin).read().iso()
+    }
+}
+
+/// PUE and PDE for a pull setting
+pub(crate) const fn pull_bits(pull: Pull) -> (bool, bool) {
+    match pull {
+        Pull::None => (false, false),
+        Pull::Up => (true, false),
+        Pull::Down => (false, true),
+        Pull::BusKeep => (true, true),
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{Pad, PadConfig, SlewRate};
+    use crate::backend::sim;
+    use crate::gpio::Pull;
+    use crate::pac::pads_bank0::GpioDrive;
+    use crate::pac::PADS_BANK0;
+
+    #[test]
+    fn test_configure_keeps_isolation() {
+        sim::reset();
+        let pad = Pad::new(18);
+        pad.set_isolated(true);
+        let config = PadConfig::new().drive(GpioDrive::_12ma).slew(SlewRate::Fast).pull(Pull::Up);
+        pad.configure(&config);
+        // ISO | IE | 12 mA | PUE | SCHMITT | SLEWFAST
+        assert_eq!(sim::peek(PADS_BANK0.gpio(18).addr()), 0x17b);
+        assert_eq!(pad.config(), config);
+
+        pad.set_isolated(false);
+        assert!(!pad.is_isolated());
+        assert_eq!(pad.config(), config);
+    }
+
+    #[test]
+    fn test_single_settings() {
+        sim::reset();
+        let pad = Pad::new(2);
+        pad.set_drive(GpioDrive::_2ma);
+        pad.set_output_disable(true);
+        pad.set_pull(Pull::BusKeep);
+        let config = pad.config();
+        assert_eq!(config.drive, GpioDrive::_2ma);
+        assert!(config.output_disable);
+        assert_eq!(config.pull, Pull::BusKeep);
+    }
+}
@@ -0,0 +1,408 @@
+//! QSPI bank
+//!
+//! The six QSPI pins (SCLK, SS, SD0 to SD3) normally carry the flash interface, but like bank 0
+//! they can be handed to the SIO and used as GPIOs: their levels sit in the upper bits of the
+//! `GPIO_HI_*` registers and their events raise IO_IRQ_QSPI instead of IO_IRQ_BANK0.
+//!
+//! Taking a QSPI pin cuts the flash off until the pin is dropped, so whatever runs meanwhile
+//! (code, constants, interrupt handlers) must live in RAM.
+//!
+//! ```ignore
+//! let ss = unsafe { QspiIo::<Input<PullUp>>::take(QspiPin::Ss) }.unwrap();
+//! let pressed = ss.is_low();
+//! drop(ss);
+//! ```
+
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use crate::gpio::irq::{Core, Events};
+use crate::gpio::mode::{
+    Floating, Input, OpenDrain, Output, OutputType, PullDown, PullType, PullUp, PushPull,
+};
+use crate::gpio::pad::pull_bits;
+use crate::gpio::sio::SioBank;
+use crate::gpio::Funcsel;
+use crate::interrupts::{nvic_enable, set_irq_handler, Interrupt};
+use crate::pac::io_qspi::GpioQspiSsCtrl;
+use crate::pac::pads_qspi::GpioQspiSs;
+use crate::pac::{IO_QSPI, PADS_QSPI};
+use crate::registers::Reg;
+
+/// Pins of the QSPI bank
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QspiPin {
+    Sclk,
+    /// Flash chip select, also wired to the BOOTSEL button on Pico boards
+    Ss,
+    Sd0,
+    Sd1,
+    Sd2,
+    Sd3,
+}
+
+impl QspiPin {
+    pub const ALL: [QspiPin; 6] = [
+        QspiPin::Sclk,
+        QspiPin::Ss,
+        QspiPin::Sd0,
+        QspiPin::Sd1,
+        QspiPin::Sd2,
+        QspiPin::Sd3,
+    ];
+
+    /// Position in IO_QSPI and in the upper SIO bits, after the two USB PHY pins
+    const fn slot(self) -> usize {
+        2 + self as usize
+    }
+
+    /// The pin's bit in the high SIO registers
+    pub const fn mask(self) -> u32 {
+        1 << (24 + self.slot())
+    }
+
+    /// The pin's control register, every QSPI pin shares the SS layout
+    pub(crate) const fn ctrl(self) -> Reg<GpioQspiSsCtrl> {
+        Reg::at(IO_QSPI.base() + 0x4 + 8 * self.slot())
+    }
+
+    /// The pin's pad, every QSPI pad shares the SS layout
+    pub(crate) const fn pad(self) -> Reg<GpioQspiSs> {
+        let offset = match self {
+            QspiPin::Sclk => 0x4,
+            QspiPin::Ss => 0x18,
+            QspiPin::Sd0 | QspiPin::Sd1 | QspiPin::Sd2 | QspiPin::Sd3 => {
+                0x8 + 4 * (self as usize - QspiPin::Sd0 as usize)
+            }
+        };
+        Reg::at(PADS_QSPI.base() + offset)
+    }
+}
+
+/// QSPI pins currently owned, one bit per [`QspiPin`]
+static TAKEN: AtomicU32 = AtomicU32::new(0);
+
+/// A mode a QSPI pin can be put in
+pub trait QspiMode {
+    /// Configures `pin` for the mode
+    fn configure(pin: QspiPin);
+}
+
+impl<P: PullType> QspiMode for Input<P> {
+    fn configure(pin: QspiPin) {
+        SioBank::Hi.clear_output_enable(pin.mask());
+        let (up, down) = pull_bits(P::PULL);
+        pin.pad().modify(|_, w| w.pue().bit(up).pde().bit(down));
+        route(pin);
+    }
+}
+
+impl<T: OutputType> QspiMode for Output<T> {
+    fn configure(pin: QspiPin) {
+        T::configure(SioBank::Hi, pin.mask());
+        route(pin);
+    }
+}
+
+/// Hands `pin` to the SIO with a usable pad: output allowed, input on, then released
+fn route(pin: QspiPin) {
+    pin.pad().modify(|_, w| w.od().clear_bit().ie().set_bit());
+    pin.ctrl().write_with_zero(|w| w.funcsel().bits(Funcsel::Sio as u32));
+    pin.pad().modify(|_, w| w.iso().clear_bit());
+}
+
+/// A QSPI pin in mode `M`, given back to the flash interface once dropped
+pub struct QspiIo<M = Output<PushPull>> {
+    pin: QspiPin,
+    /// Pad and control registers as the flash interface had them, restored on drop
+    saved: (u32, u32),
+    _mode: PhantomData<M>,
+}
+
+impl<M: QspiMode> QspiIo<M> {
+    /// Takes a QSPI pin and configures it for its mode, `None` if it's already taken
+    ///
+    /// # Safety
+    ///
+    /// The flash can't be read until the pin is dropped: the caller and everything that may
+    /// interrupt it must run from RAM, with nothing else accessing the flash
+    ///
+    /// `pin`: the pin to take
+    pub unsafe fn take(pin: QspiPin) -> Option<Self> {
+        let bit = 1 << pin as u32;
+        if TAKEN.fetch_or(bit, Ordering::AcqRel) & bit != 0 {
+            return None;
+        }
+        let saved = (pin.pad().read().bits(), pin.ctrl().read().bits());
+        M::configure(pin);
+        Some(Self { pin, saved, _mode: PhantomData })
+    }
+}
+
+impl<M> QspiIo<M> {
+    /// The pin
+    pub fn pin(&self) -> QspiPin {
+        self.pin
+    }
+
+    /// Reconfigures the pin for another mode
+    pub fn into_mode<M2: QspiMode>(self) -> QspiIo<M2> {
+        let (pin, saved) = (self.pin, self.saved);
+        // The pin changes mode, it isn't given back
+        core::mem::forget(self);
+        M2::configure(pin);
+        QspiIo { pin, saved, _mode: PhantomData }
+    }
+
+    /// Input with a pull-up
+    pub fn into_pull_up_input(self) -> QspiIo<Input<PullUp>> {
+        self.into_mode()
+    }
+
+    /// Input with a pull-down
+    pub fn into_pull_down_input(self) -> QspiIo<Input<PullDown>> {
+        self.into_mode()
+    }
+
+    /// Input without pulls
+    pub fn into_floating_input(self) -> QspiIo<Input<Floating>> {
+        self.into_mode()
+    }
+
+    /// Output driving both levels
+    pub fn into_push_pull_output(self) -> QspiIo<Output<PushPull>> {
+        self.into_mode()
+    }
+
+    /// Output only driving low
+    pub fn into_open_drain_output(self) -> QspiIo<Output<OpenDrain>> {
+        self.into_mode()
+    }
+
+    /// Level on the pin, 1 for high and 0 for low
+    pub fn value(&self) -> u32 {
+        (SioBank::Hi.input() & self.pin.mask() != 0) as u32
+    }
+
+    /// Whether the pin is high
+    pub fn is_high(&self) -> bool {
+        self.value() != 0
+    }
+
+    /// Whether the pin is low
+    pub fn is_low(&self) -> bool {
+        self.value() == 0
+    }
+
+    /// Interrupts the calling core on `events` and runs `callback` from the dispatcher
+    ///
+    /// The dispatcher must be installed, see [`install`].
+    ///
+    /// `events`: the events to enable
+    /// `callback`: the handler
+    pub fn enable_interrupt(&self, events: Events, callback: QspiCallback) {
+        set_callback(self.pin, callback);
+        enable(self.pin, events, Core::current());
+    }
+
+    /// Stops interrupting the calling core on `events`
+    ///
+    /// `events`: the events to disable
+    pub fn disable_interrupt(&self, events: Events) {
+        disable(self.pin, events, Core::current());
+    }
+}
+
+impl<T: OutputType> QspiIo<Output<T>> {
+    /// Set the pin high
+    pub fn set(&self) {
+        T::set(SioBank::Hi, self.pin.mask());
+    }
+
+    /// Set the pin low
+    pub fn clear(&self) {
+        T::clear(SioBank::Hi, self.pin.mask());
+    }
+
+    /// Toggle the pin
+    pub fn toggle(&self) {
+        T::toggle(SioBank::Hi, self.pin.mask());
+    }
+}
+
+impl<M> Drop for QspiIo<M> {
+    /// Gives the pin back to the flash interface
+    fn drop(&mut self) {
+        disable(self.pin, Events::LEVEL_LOW | Events::LEVEL_HIGH | Events::EDGES, Core::current());
+        SioBank::Hi.clear_output_enable(self.pin.mask());
+        let (pad, ctrl) = self.saved;
+        self.pin.pad().write(|w| w.bits(pad));
+        self.pin.ctrl().write(|w| w.bits(ctrl));
+        TAKEN.fetch_and(!(1 << self.pin as u32), Ordering::AcqRel);
+    }
+}
+
+/// Called from the dispatcher with the pin and the events that fired
+pub type QspiCallback = fn(pin: QspiPin, events: Events);
+
+/// Registered callbacks as function addresses, 0 when there is none
+static CALLBACKS: [AtomicUsize; 6] = [const { AtomicUsize::new(0) }; 6];
+
+/// Registers the callback the dispatcher calls for a pin, replacing the previous one
+///
+/// `pin`: the QSPI pin
+/// `callback`: the handler
+pub fn set_callback(pin: QspiPin, callback: QspiCallback) {
+    CALLBACKS[pin as usize].store(callback as usize, Ordering::Release);
+}
+
+/// Removes the callback of a pin, its events are still acknowledged
+///
+/// `pin`: the QSPI pin
+pub fn clear_callback(pin: QspiPin) {
+    CALLBACKS[pin as usize].store(0, Ordering::Release);
+}
+
+/// Enables events of a pin on a core, stale edges are acknowledged first
+///
+/// `pin`: the QSPI pin
+/// `events`: the events to enable
+/// `core`: the core to interrupt
+pub fn enable(pin: QspiPin, events: Events, core: Core) {
+    acknowledge(pin, events);
+    let mask = events.bits() << (4 * pin.slot());
+    IO_QSPI.proc_inte(core as usize).set_bits(|w| w.bits(mask));
+}
+
+/// Disables events of a pin on a core
+///
+/// `pin`: the QSPI pin
+/// `events`: the events to disable
+/// `core`: the core to stop interrupting
+pub fn disable(pin: QspiPin, events: Events, core: Core) {
+    let mask = events.bits() << (4 * pin.slot());
+    IO_QSPI.proc_inte(core as usize).clear_bits(|w| w.bits(mask));
+}
+
+/// Clears latched edges of a pin, levels clear themselves once the level changes
+///
+/// `pin`: the QSPI pin
+/// `events`: the events to acknowledge
+pub fn acknowledge(pin: QspiPin, events: Events) {
+    let edges = events.bits() & Events::EDGES.bits();
+    if edges != 0 {
+        IO_QSPI.intr().write(|w| w.bits(edges << (4 * pin.slot())));
+    }
+}
+
+/// Events of a pin currently interrupting a core
+///
+/// `pin`: the QSPI pin
+/// `core`: the interrupted core
+pub fn pending(pin: QspiPin, core: Core) -> Events {
+    Events::from_bits(IO_QSPI.proc_ints(core as usize).read().bits() >> (4 * pin.slot()))
+}
+
+/// Installs the dispatcher on IO_IRQ_QSPI and enables it on the calling core
+///
+/// Call it from each core whose events should be handled.
+///
+/// # Safety
+///
+/// This function edits the vector table in ram, see [`set_irq_handler`]
+pub unsafe fn install() {
+    set_irq_handler(Interrupt::IO_IRQ_QSPI, dispatch);
+    nvic_enable(Interrupt::IO_IRQ_QSPI);
+}
+
+/// IO_IRQ_QSPI handler: acknowledges the edges and runs the callbacks of the calling core
+fn dispatch() {
+    let ints = IO_QSPI.proc_ints(Core::current() as usize).read().bits();
+    // Acknowledge before calling back, so an edge during the callback isn't lost
+    let edges = ints & (Events::EDGES.bits() * 0x1111_1111);
+    if edges != 0 {
+        IO_QSPI.intr().write(|w| w.bits(edges));
+    }
+
+    for pin in QspiPin::ALL {
+        let events = Events::from_bits(ints >> (4 * pin.slot()));
+        if events.is_empty() {
+            continue;
+        }
+        let raw = CALLBACKS[pin as usize].load(Ordering::Acquire);
+        if raw != 0 {
+            // SAFETY: a non-zero address is always a `QspiCallback` stored by `set_callback`
+            let callback = unsafe { core::mem::transmute::<usize, QspiCallback>(raw) };
+            callback(pin, events);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{dispatch, set_callback, QspiIo, QspiPin};
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use crate::backend::sim;
+    use crate::gpio::irq::Events;
+    use crate::gpio::mode::{Input, PullDown, PullUp};
+    use crate::gpio::Funcsel;
+    use crate::pac::{IO_QSPI, PADS_QSPI, SIO};
+
+    #[test]
+    fn test_pin_registers() {
+        assert_eq!(QspiPin::Sclk.ctrl().addr(), IO_QSPI.gpio_qspi_sclk_ctrl().addr());
+        assert_eq!(QspiPin::Sd2.ctrl().addr(), IO_QSPI.gpio_qspi_sd_ctrl(2).addr());
+        assert_eq!(QspiPin::Ss.pad().addr(), PADS_QSPI.gpio_qspi_ss().addr());
+        assert_eq!(QspiPin::Sd3.pad().addr(), PADS_QSPI.gpio_qspi_sd(3).addr());
+        assert_eq!(QspiPin::Ss.mask(), 1 << 27);
+    }
+
+    #[test]
+    fn test_take_and_give_back() {
+        let _pins = sim::exclusive();
+        sim::reset();
+        // As the boot ROM leaves them: flash function, SS pulled up
+        sim::poke(IO_QSPI.gpio_qspi_ss_ctrl().addr(), 0);
+        sim::poke(PADS_QSPI.gpio_qspi_ss().addr(), 0x5a);
+        let ss = unsafe { QspiIo::<Input<PullUp>>::take(QspiPin::Ss) }.unwrap();
+        assert!(unsafe { QspiIo::<Input<PullUp>>::take(QspiPin::Ss) }.is_none());
+        assert_eq!(IO_QSPI.gpio_qspi_ss_ctrl().read().funcsel().bits(), Funcsel::Sio as u32);
+        assert!(PADS_QSPI.gpio_qspi_ss().read().pue());
+        sim::poke(SIO.gpio_hi_in().addr(), 1 << 27);
+        assert!(ss.is_high());
+
+        let ss = ss.into_push_pull_output();
+        ss.clear();
+        assert_eq!(SIO.gpio_hi_oe().read().bits(), 1 << 27);
+        drop(ss);
+        assert_eq!(IO_QSPI.gpio_qspi_ss_ctrl().read().bits(), 0);
+        assert_eq!(PADS_QSPI.gpio_qspi_ss().read().bits(), 0x5a);
+        assert_eq!(SIO.gpio_hi_oe().read().bits(), 0);
+
+        // A pull-down doesn't stay on the flash chip select
+        let ss = unsafe { QspiIo::<Input<PullDown>>::take(QspiPin::Ss) }.unwrap();
+        assert!(PADS_QSPI.gpio_qspi_ss().read().pde());
+        drop(ss);
+        let pad = PADS_QSPI.gpio_qspi_ss().read();
+        assert!(pad.pue() && !pad.pde());
+        assert_eq!(pad.bits(), 0x5a);
+    }
+
+    #[test]
+    fn test_dispatch_calls_back_and_acknowledges() {
+        static SEEN: AtomicU32 = AtomicU32::new(0);
+        fn on_edge(pin: QspiPin, events: Events) {
+            SEEN.store((pin as u32) << 4 | events.bits(), Ordering::Relaxed);
+        }
+
+        sim::reset();
+        set_callback(QspiPin::Sd1, on_edge);
+        // SD1 comes after the USB PHY pins, SCLK, SS and SD0
+        sim::poke(IO_QSPI.intr().addr(), 0x4 << 20);
+        sim::poke(IO_QSPI.proc_ints(0).addr(), 0x4 << 20);
+
+        dispatch();
+        assert_eq!(SEEN.load(Ordering::Relaxed), (QspiPin::Sd1 as u32) << 4 | Events::EDGE_FALL.bits());
+        assert_eq!(IO_QSPI.intr().read().bits(), 0);
+    }
+}
@@ -0,0 +1,147 @@
+//! SIO GPIO registers
+//!
+//! The SIO drives and reads the pins in two halves: GPIO0 to 31 sit in `GPIO_IN`/`GPIO_OUT`/
+//! `GPIO_OE`, GPIO32 to 47 in the low bits of their `GPIO_HI_*` twins. Each output register
+//! has set, clear and xor companions, so pins sharing a half change in a single write.
+
+use crate::pac::SIO;
+
+/// One half of the SIO GPIO registers
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SioBank {
+    /// GPIO0 to 31
+    Lo,
+    /// GPIO32 to 47, then the QSPI and USB pins in the upper bits
+    Hi,
+}
+
+impl SioBank {
+    /// The half holding GPIO `pin`
+    ///
+    /// `pin`: the GPIO number
+    pub const fn of(pin: usize) -> Self {
+        if pin < 32 { SioBank::Lo } else { SioBank::Hi }
+    }
+
+    /// GPIO `pin`'s bit in its half
+    ///
+    /// `pin`: the GPIO number
+    pub const fn mask(pin: usize) -> u32 {
+        1 << (pin % 32)
+    }
+
+    /// Levels on the pins
+    pub fn input(self) -> u32 {
+        match self {
+            SioBank::Lo => SIO.gpio_in().read().bits(),
+            SioBank::Hi => SIO.gpio_hi_in().read().bits(),
+        }
+    }
+
+    /// Levels the pins are driven to
+    pub fn output(self) -> u32 {
+        match self {
+            SioBank::Lo => SIO.gpio_out().read().bits(),
+            SioBank::Hi => SIO.gpio_hi_out().read().bits(),
+        }
+    }
+
+    /// Drives the pins in `mask` high
+    ///
+    /// `mask`: the pins' bits
+    pub fn set_output(self, mask: u32) {
+        match self {
+            SioBank::Lo => SIO.gpio_out_set().write(|w| w.bits(mask)),
+            SioBank::Hi => SIO.gpio_hi_out_set().write(|w| w.bits(mask)),
+        };
+    }
+
+    /// Drives the pins in `mask` low
+    ///
+    /// `mask`: the pins' bits
+    pub fn clear_output(self, mask: u32) {
+        match self {
+            SioBank::Lo => SIO.gpio_out_clr().write(|w| w.bits(mask)),
+            SioBank::Hi => SIO.gpio_hi_out_clr().write(|w| w.bits(mask)),
+        };
+    }
+
+    /// Inverts the pins in `mask`
+    ///
+    /// `mask`: the pins' bits
+    pub fn toggle_output(self, mask: u32) {
+        match self {
+            SioBank::Lo => SIO.gpio_out_xor().write(|w| w.bits(mask)),
+            SioBank::Hi => SIO.gpio_hi_out_xor().write(|w| w.bits(mask)),
+        };
+    }
+
+    /// Pins whose output is enabled
+    pub fn output_enable(self) -> u32 {
+        match self {
+            SioBank::Lo => SIO.gpio_oe().read().bits(),
+            SioBank::Hi => SIO.gpio_hi_oe().read().bits(),
+        }
+    }
+
+    /// Enables the output of the pins in `mask`
+    ///
+    /// `mask`: the pins' bits
+    pub fn set_output_enable(self, mask: u32) {
+        match self {
+            SioBank::Lo => SIO.gpio_oe_set().write(|w| w.bits(mask)),
+            SioBank::Hi => SIO.gpio_hi_oe_set().write(|w| w.bits(mask)),
+        };
+    }
+
+    /// Disables the output of the pins in `mask`
+    ///
+    /// `mask`: the pins' bits
+    pub fn clear_output_enable(self, mask: u32) {
+        match self {
+            SioBank::Lo => SIO.gpio_oe_clr().write(|w| w.bits(mask)),
+            SioBank::Hi => SIO.gpio_hi_oe_clr().write(|w| w.bits(mask)),
+        };
+    }
+
+    /// Inverts the output enable of the pins in `mask`
+    ///
+    /// `mask`: the pins' bits
+    pub fn toggle_output_enable(self, mask: u32) {
+        match self {
+            SioBank::Lo => SIO.gpio_oe_xor().write(|w| w.bits(mask)),
+            SioBank::Hi => SIO.gpio_hi_oe_xor().write(|w| w.bits(mask)),
+        };
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::SioBank;
+    use crate::backend::sim;
+    use crate::pac::SIO;
+
+    #[test]
+    fn test_banks_split_at_32() {
+        assert_eq!(SioBank::of(31), SioBank::Lo);
+        assert_eq!(SioBank::of(32), SioBank::Hi);
+        assert_eq!(SioBank::mask(31), 1 << 31);
+        assert_eq!(SioBank::mask(47), 1 << 15);
+    }
+
+    #[test]
+    fn test_high_bank_registers() {
+        sim::reset();
+        SioBank::Hi.set_output(0b101);
+        SioBank::Hi.toggle_output(0b011);
+        SioBank::Hi.set_output_enable(0b110);
+        assert_eq!(SIO.gpio_hi_out().read().bits(), 0b110);
+        assert_eq!(SioBank::Hi.output_enable(), 0b110);
+        assert_eq!(SIO.gpio_out().read().bits(), 0);
+        assert_eq!(SIO.gpio_oe().read().bits(), 0);
+
+        sim::poke(SIO.gpio_hi_in().addr(), 1 << 15);
+        assert_eq!(SioBank::Hi.input(), 1 << 15);
+        assert_eq!(SioBank::Lo.input(), 0);
+    }
+}
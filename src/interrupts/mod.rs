@@ -1,9 +1,11 @@
 //! Interrupts module
 
-mod regs;
+use crate::pac::{PPB, PPB_NS};
+use crate::reg_read;
 
-use crate::interrupts::regs::*;
-use crate::register;
+// Vector tables
+pub const FLASH_BASE: usize = 0x10000000;
+pub const VTABLE_FIRST_IRQ: usize = 16;
 
 #[no_mangle]
 pub static mut RAM_VECTOR_TABLE: [u32; 96] = [0; 96];
@@ -124,6 +126,7 @@ fn interrupt_num(num: Interrupt) -> usize {
 }
 
 /// Determine if the CPU is running in Secure mode (Cortex-M33 TrustZone)
+#[cfg(target_arch = "arm")]
 #[inline(always)]
 fn is_secure() -> bool {
     let control: u32;
@@ -134,6 +137,13 @@ fn is_secure() -> bool {
     (control & (1 << 0)) == 0
 }
 
+/// The host has no TrustZone, behave like the secure world
+#[cfg(not(target_arch = "arm"))]
+#[inline(always)]
+fn is_secure() -> bool {
+    true
+}
+
 /// Data memory barrier (for proper synchronization after VTOR writes)
 ///
 /// # Safety
@@ -141,6 +151,7 @@ fn is_secure() -> bool {
 /// Raw assembly instruction that might not return
 #[inline(always)]
 unsafe fn dmb() {
+    #[cfg(target_arch = "arm")]
     core::arch::asm!("dmb ish", options(nomem, nostack, preserves_flags));
 }
 
@@ -151,20 +162,19 @@ unsafe fn dmb() {
 /// Raw assembly instruction that might not return
 #[inline(always)]
 unsafe fn isb() {
+    #[cfg(target_arch = "arm")]
     core::arch::asm!("isb", options(nomem, nostack, preserves_flags));
 }
 
 /// Read the current VTOR (Vector Table Offset Register)
 #[inline(always)]
 pub fn vtor_read() -> usize {
-    let base = if is_secure() {
-        PPB_BASE_SECURE
+    let ppb = if is_secure() {
+        PPB
     } else {
-        PPB_BASE_NON_SECURE
+        PPB_NS
     };
-    unsafe {
-        core::ptr::read_volatile((base + VTOR_OFFSET) as *const usize)
-    }
+    ppb.vtor().read().bits() as usize
 }
 
 /// Write a new base address to VTOR
@@ -180,14 +190,14 @@ pub fn vtor_read() -> usize {
 /// if the address is not valid, CPU will irrecoverably fault!
 #[inline(always)]
 pub unsafe fn vtor_write(addr: u32) {
-    let base = if is_secure() {
-        PPB_BASE_SECURE
+    let ppb = if is_secure() {
+        PPB
     } else {
-        PPB_BASE_NON_SECURE
+        PPB_NS
     };
 
     // VTOR requires 128-byte alignment (7 bits clear)
-    core::ptr::write_volatile((base + VTOR_OFFSET) as *mut u32, addr & !0x7F);
+    ppb.vtor().write(|w| w.tbloff().bits(addr >> 7));
     dmb();
     isb();
 }
@@ -203,22 +213,21 @@ pub unsafe fn vtor_write(addr: u32) {
 pub unsafe fn set_irq_handler(irq: Interrupt, handler: fn()) {
     let irq_num = interrupt_num(irq);
     let index = VTABLE_FIRST_IRQ + irq_num;
-    RAM_VECTOR_TABLE[index] = handler as *const () as u32;
+    RAM_VECTOR_TABLE[index] = handler as usize as u32;
 
     // switch to the RAM table
-    let table_addr = &raw const RAM_VECTOR_TABLE as *const _ as u32;
+    let table_addr = &raw const RAM_VECTOR_TABLE as usize as u32;
     vtor_write(table_addr);
 }
 
 /// Copy an existing flash vector table into RAM (for dynamic updates)
 pub fn copy_vector_table_to_ram() {
     const LEN: usize = size_of::<[u32; 96]>() / size_of::<u32>();
-    let flash_vtable = FLASH_BASE as *const u32;
     unsafe {
         let ram_vtable = core::ptr::addr_of!(RAM_VECTOR_TABLE) as *mut u32;
 
         for i in 0..LEN {
-            let value = core::ptr::read_volatile(flash_vtable.add(i));
+            let value = reg_read(FLASH_BASE + i * size_of::<u32>()) as u32;
             core::ptr::write_volatile(ram_vtable.add(i), value);
         }
     }
@@ -232,6 +241,5 @@ pub fn copy_vector_table_to_ram() {
 /// - 'irq'
 pub unsafe fn nvic_enable(irq: Interrupt) {
     let irq_num = interrupt_num(irq);
-    let iser = PPB_BASE_SECURE + NVIC_ISER0 + 4 * (irq_num / 32);
-    core::ptr::write_volatile(register(iser), 1usize << (irq_num % 32));
+    PPB.nvic_iser(irq_num / 32).write(|w| w.bits(1 << (irq_num % 32)));
 }
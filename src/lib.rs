@@ -1,25 +1,35 @@
 #![no_std]
-#![no_main]
-#![feature(custom_test_frameworks)]
-#![test_runner(crate::test_runner)]
-#![reexport_test_harness_main = "test_main"]
-
+#![cfg_attr(target_os = "none", no_main)]
+#![cfg_attr(target_os = "none", feature(custom_test_frameworks))]
+#![cfg_attr(target_os = "none", test_runner(crate::test_runner))]
+#![cfg_attr(target_os = "none", reexport_test_harness_main = "test_main")]
+
+pub mod backend;
+pub mod registers;
+pub mod pac;
+pub mod resets;
 pub mod spinlocks;
 pub mod clocks;
 pub mod timers;
 pub mod gpio;
 pub mod uart;
 pub mod interrupts;
+pub mod system;
+pub mod board;
 
+#[cfg(target_os = "none")]
 use core::panic::PanicInfo;
-use core::{fmt, ptr};
+use core::fmt;
 use core::fmt::Write;
-use crate::clocks::{configure_clk_ref, configure_clk_sys, init_pll, init_xosc};
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::backend::{ActiveBackend, RegisterBackend};
+use crate::gpio::Pins;
+#[cfg(target_os = "none")]
 use crate::gpio::Pin;
-use crate::timers::start_timers;
-use crate::interrupts::copy_vector_table_to_ram;
+use crate::resets::Reset;
+use crate::system::SystemConfig;
 
-#[cfg(test)]
+#[cfg(all(test, target_os = "none"))]
 use cortex_m_rt::entry;
 
 // Atomic register operations offsets
@@ -27,11 +37,6 @@ pub const ATOMIC_XOR: usize = 0x1000;
 pub const ATOMIC_SET: usize = 0x2000;
 pub const ATOMIC_CLEAR: usize = 0x3000;
 
-// Reset registers
-pub const RESETS_BASE:       usize = 0x4002_0000;
-pub const RESETS_RESET:      usize = RESETS_BASE;
-pub const RESETS_RESET_DONE: usize = RESETS_BASE + 0x8;
-
 
 // ---------- Helpers ----------
 
@@ -43,6 +48,9 @@ pub const fn bit(n: usize) -> usize { (1u32 << n) as usize }
 
 /// takes a memory address and returns a pointer to a 32 bits register
 ///
+/// Accesses through this pointer bypass the register backend,
+/// prefer [`reg_read`] and [`reg_write`]
+///
 /// `addr`: the memory address to point to
 #[inline(always)]
 pub fn register(addr: usize) -> *mut usize { addr as *mut usize }
@@ -53,9 +61,7 @@ pub fn register(addr: usize) -> *mut usize { addr as *mut usize }
 /// `value`: value to write to register
 #[inline(always)]
 pub fn reg_write(addr: usize, val: usize) {
-    unsafe {
-        ptr::write_volatile(addr as *mut usize, val);
-    }
+    ActiveBackend::write(addr, val as u32);
 }
 
 /// Reads from a register given its memory address
@@ -63,9 +69,7 @@ pub fn reg_write(addr: usize, val: usize) {
 /// `addr`: register's memory address
 #[inline(always)]
 pub fn reg_read(addr: usize) -> usize {
-    unsafe {
-        ptr::read_volatile(addr as *const usize)
-    }
+    ActiveBackend::read(addr) as usize
 }
 
 /// Busy wait forever, an explicit wrapper around loop {}
@@ -124,52 +128,75 @@ macro_rules! println {
 }
 
 
-/// Resets the IO banks and pads
+/// Brings the IO banks and pads out of reset for the rest of the program
 pub fn reset_peripherals() {
-    reg_write(RESETS_RESET + ATOMIC_CLEAR, bit(6) | bit(9));
-    while (reg_read(RESETS_RESET_DONE) & (bit(6) | bit(9))) != (bit(6) | bit(9)) {}
+    resets::acquire(Reset::IoBank0).leak();
+    resets::acquire(Reset::PadsBank0).leak();
+}
+
+/// The chip's peripherals, handed out once by [`Peripherals::take`]
+pub struct Peripherals {
+    pub pins: Pins,
+}
+
+static PERIPHERALS_TAKEN: AtomicBool = AtomicBool::new(false);
+
+impl Peripherals {
+    /// Takes the peripherals, `None` after the first call or if a pin is already taken
+    pub fn take() -> Option<Self> {
+        if PERIPHERALS_TAKEN.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        match Pins::take() {
+            Some(pins) => Some(Self { pins }),
+            None => {
+                // Someone holds a pin, try again once it's dropped
+                PERIPHERALS_TAKEN.store(false, Ordering::Release);
+                None
+            }
+        }
+    }
 }
 
 /// Basic panic handler
 ///
 /// `info`: information about the panic
+#[cfg(target_os = "none")]
 #[panic_handler]
 unsafe fn panic(info: &PanicInfo) -> ! {
-    let led = Pin::<2>::take();
-    led.set();
+    // The application may own the LED, or be panicking in the middle of taking it
+    if let Some(led) = Pin::<2>::try_take() {
+        led.set();
+    }
 
     println!("{}", info);
     nop_loop();
 }
 
-/// Initializes the rp2350 in this order:
+/// Initializes the rp2350 with the default [`SystemConfig`], in this order:
 ///
 /// - resets the peripherals
-/// - initializes the XOSC
-/// - initializes the PLL
-/// - configures the reference clock
-/// - configures the system clock
+/// - initializes the XOSC and configures the reference clock
+/// - initializes PLL_SYS at 150 MHz and configures the system clock
+/// - configures the peripheral clock
+/// - initializes PLL_USB and configures the USB and ADC clocks
 /// - starts the timers
 /// - copies the vector table entries to RAM
+///
+/// Use [`SystemConfig`] directly for other crystals, clock speeds or peripherals.
 #[inline(always)]
 pub fn init() {
-    unsafe {
-        reset_peripherals();
-        init_xosc();
-        init_pll();
-        configure_clk_ref();
-        configure_clk_sys();
-        start_timers();
-        copy_vector_table_to_ram()
-    }
+    // The default configuration validates and its dividers fit their generators, a failure
+    // means the tree itself is broken
+    unsafe { SystemConfig::new().init() }.expect("the default system configuration boots");
 }
 
-#[cfg(test)]
+#[cfg(all(test, target_os = "none"))]
 #[entry]
 fn main() -> ! {
     unsafe {
         init();
-        uart::uart_init(115200);
+        let _ = uart::uart_init(115200);
 
         test_main();
 
@@ -202,7 +229,7 @@ pub fn test_runner(tests: &[&dyn Testable]) {
 }
 
 
-#[cfg(test)]
+#[cfg(all(test, target_os = "none"))]
 mod tests {
     use super::{bit, print, println};
     use super::uart::{puts};
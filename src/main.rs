@@ -6,9 +6,10 @@
 
 use cortex_m_rt::entry;
 
-use rp_rs::{init, interrupts, println, uart};
+use rp_rs::{interrupts, println, uart};
 use rp_rs::gpio::Pin;
 use rp_rs::interrupts::{nvic_enable, Interrupt};
+use rp_rs::system::SystemConfig;
 use rp_rs::timers::wait_ms;
 
 
@@ -25,8 +26,9 @@ fn on_uart_rx() {
 #[entry]
 fn main() -> ! {
     unsafe {
-        init();
-        uart::uart_init(115200);
+        let Ok(_system) = SystemConfig::new().uart0(115200).init() else {
+            rp_rs::nop_loop();
+        };
 
         let led = Pin::<25>::take();
         println!("Hello, World!");
@@ -0,0 +1,37 @@
+//! Peripheral access
+//!
+//! Generated at build time from `svd/rp2350.svd` by `build/svd.rs`: one module per peripheral
+//! with its [`register_block!`](crate::register_block), [`register!`](crate::register)s and
+//! [`field_values!`](crate::field_values), and one constant per peripheral instance.
+//!
+//! ```ignore
+//! use crate::pac::{clocks::ClkRefCtrlSrc, CLOCKS};
+//!
+//! CLOCKS.clk_ref_ctrl().modify(|_, w| w.src().variant(ClkRefCtrlSrc::XoscClksrc));
+//! ```
+//!
+//! Peripherals derived from another one share its module (`UART1` is a `uart::Uart`), and
+//! numbered registers with the same layout are register arrays (`IO_BANK0.gpio_ctrl(n)`).
+
+include!(concat!(env!("OUT_DIR"), "/pac.rs"));
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::*;
+
+    #[test]
+    fn test_derived_peripherals_share_layout() {
+        assert_eq!(PLL_USB.prim().addr(), 0x4005_800c);
+        assert_eq!(UART1.uartfr().addr(), 0x4007_8018);
+        assert_eq!(PPB_NS.vtor().addr(), 0xe002_ed08);
+    }
+
+    #[test]
+    fn test_numbered_registers_fold_into_arrays() {
+        assert_eq!(IO_BANK0.gpio_ctrl(25).addr(), 0x4002_80cc);
+        assert_eq!(PADS_BANK0.gpio(47).addr(), 0x4003_80c0);
+        assert_eq!(CLOCKS.clk_gpout_div(3).addr(), 0x4001_0028);
+        assert_eq!(TICKS.timer_cycles(1).addr(), 0x4010_8028);
+        assert_eq!(SIO.spinlock(31).addr(), 0xd000_017c);
+    }
+}
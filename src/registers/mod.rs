@@ -0,0 +1,552 @@
+//! Typed register access
+//!
+//! Peripherals are described with [`register_block!`](crate::register_block),
+//! [`register!`](crate::register) and [`field_values!`](crate::field_values).
+//! A block hands out [`Reg`]s at fixed addresses, and every register has named fields
+//! that are read through [`R`] and written through [`W`]:
+//!
+//! ```ignore
+//! CLOCKS.clk_peri_ctrl().modify(|_, w| w.auxsrc().variant(ClkPeriAuxsrc::XoscClksrc).enable().set_bit());
+//! let fbdiv = PLL_SYS.fbdiv_int().read().fbdiv_int().bits();
+//! ```
+//!
+//! All accesses go through [`reg_read`] and [`reg_write`], so they work on any backend.
+
+use core::marker::PhantomData;
+
+use crate::{reg_read, reg_write, ATOMIC_CLEAR, ATOMIC_SET, ATOMIC_XOR};
+
+/// Layout of one register
+pub trait RegisterSpec {
+    /// Value of the register out of reset, [`Reg::write`] starts from it
+    const RESET: u32;
+}
+
+/// Enumerated values of a register field
+pub trait FieldValue: Copy {
+    /// Width in bits of the field the values belong to
+    const WIDTH: u8;
+
+    /// Raw value to store in the field
+    fn bits(self) -> u32;
+
+    /// Decodes a raw field value, `None` if it isn't one of the enumerated values
+    fn from_bits(bits: u32) -> Option<Self>;
+}
+
+/// Mask of a `width` bits field at bit 0
+#[inline(always)]
+const fn field_mask(width: u8) -> u32 {
+    if width >= 32 { u32::MAX } else { (1 << width) - 1 }
+}
+
+/// A register with layout `S` at a fixed address
+pub struct Reg<S> {
+    addr: usize,
+    _spec: PhantomData<S>,
+}
+
+impl<S: RegisterSpec> Reg<S> {
+    /// Creates a handle to the register at `addr`
+    #[inline(always)]
+    pub const fn at(addr: usize) -> Self {
+        Self { addr, _spec: PhantomData }
+    }
+
+    /// Memory address of the register
+    #[inline(always)]
+    pub const fn addr(&self) -> usize {
+        self.addr
+    }
+
+    /// Reads the register
+    #[inline(always)]
+    pub fn read(&self) -> R<S> {
+        R::new(reg_read(self.addr) as u32)
+    }
+
+    /// Writes the register, fields not set by `f` get their reset value
+    #[inline(always)]
+    pub fn write<F>(&self, f: F)
+    where
+        F: FnOnce(&mut W<S>) -> &mut W<S>,
+    {
+        let mut w = W::new(S::RESET);
+        f(&mut w);
+        reg_write(self.addr, w.bits as usize);
+    }
+
+    /// Writes the register, fields not set by `f` are cleared
+    #[inline(always)]
+    pub fn write_with_zero<F>(&self, f: F)
+    where
+        F: FnOnce(&mut W<S>) -> &mut W<S>,
+    {
+        let mut w = W::new(0);
+        f(&mut w);
+        reg_write(self.addr, w.bits as usize);
+    }
+
+    /// Read-modify-write, fields not set by `f` keep their current value
+    #[inline(always)]
+    pub fn modify<F>(&self, f: F)
+    where
+        F: for<'w> FnOnce(&R<S>, &'w mut W<S>) -> &'w mut W<S>,
+    {
+        let r = self.read();
+        let mut w = W::new(r.bits);
+        f(&r, &mut w);
+        reg_write(self.addr, w.bits as usize);
+    }
+
+    /// Atomically sets the bits written by `f` through the SET alias
+    ///
+    /// Only valid for peripherals with atomic aliases (not SIO or the PPB)
+    #[inline(always)]
+    pub fn set_bits<F>(&self, f: F)
+    where
+        F: FnOnce(&mut W<S>) -> &mut W<S>,
+    {
+        self.alias_write(ATOMIC_SET, f);
+    }
+
+    /// Atomically clears the bits written by `f` through the CLEAR alias
+    ///
+    /// Only valid for peripherals with atomic aliases (not SIO or the PPB)
+    #[inline(always)]
+    pub fn clear_bits<F>(&self, f: F)
+    where
+        F: FnOnce(&mut W<S>) -> &mut W<S>,
+    {
+        self.alias_write(ATOMIC_CLEAR, f);
+    }
+
+    /// Atomically flips the bits written by `f` through the XOR alias
+    ///
+    /// Only valid for peripherals with atomic aliases (not SIO or the PPB)
+    #[inline(always)]
+    pub fn toggle_bits<F>(&self, f: F)
+    where
+        F: FnOnce(&mut W<S>) -> &mut W<S>,
+    {
+        self.alias_write(ATOMIC_XOR, f);
+    }
+
+    #[inline(always)]
+    fn alias_write<F>(&self, alias: usize, f: F)
+    where
+        F: FnOnce(&mut W<S>) -> &mut W<S>,
+    {
+        let mut w = W::new(0);
+        f(&mut w);
+        reg_write(self.addr + alias, w.bits as usize);
+    }
+}
+
+/// Value read from a register with layout `S`
+pub struct R<S> {
+    bits: u32,
+    _spec: PhantomData<S>,
+}
+
+impl<S> R<S> {
+    #[inline(always)]
+    const fn new(bits: u32) -> Self {
+        Self { bits, _spec: PhantomData }
+    }
+
+    /// Raw value of the whole register
+    #[inline(always)]
+    pub const fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Reads a single bit field
+    #[doc(hidden)]
+    #[inline(always)]
+    pub const fn bit_at<const OFFSET: u8>(&self) -> bool {
+        (self.bits >> OFFSET) & 1 != 0
+    }
+
+    /// Reads a multi bit field
+    #[doc(hidden)]
+    #[inline(always)]
+    pub const fn field_at<const OFFSET: u8, const WIDTH: u8, V>(&self) -> FieldReader<V> {
+        FieldReader { bits: (self.bits >> OFFSET) & field_mask(WIDTH), _value: PhantomData }
+    }
+}
+
+/// Value being written to a register with layout `S`
+pub struct W<S> {
+    bits: u32,
+    _spec: PhantomData<S>,
+}
+
+impl<S> W<S> {
+    #[inline(always)]
+    const fn new(bits: u32) -> Self {
+        Self { bits, _spec: PhantomData }
+    }
+
+    /// Overwrites the whole register value
+    #[inline(always)]
+    pub fn bits(&mut self, bits: u32) -> &mut Self {
+        self.bits = bits;
+        self
+    }
+
+    #[doc(hidden)]
+    #[inline(always)]
+    pub fn bit_at<const OFFSET: u8>(&mut self) -> BitWriter<'_, S, OFFSET> {
+        BitWriter { w: self }
+    }
+
+    #[doc(hidden)]
+    #[inline(always)]
+    pub fn field_at<const OFFSET: u8, const WIDTH: u8, V>(&mut self) -> FieldWriter<'_, S, OFFSET, WIDTH, V> {
+        FieldWriter { w: self, _value: PhantomData }
+    }
+}
+
+/// Value of a multi bit field, raw or as its enumerated values `V`
+pub struct FieldReader<V = u32> {
+    bits: u32,
+    _value: PhantomData<V>,
+}
+
+impl<V> FieldReader<V> {
+    /// Raw value of the field
+    #[inline(always)]
+    pub const fn bits(&self) -> u32 {
+        self.bits
+    }
+}
+
+impl<V: FieldValue> FieldReader<V> {
+    /// The field decoded as one of its enumerated values
+    #[inline(always)]
+    pub fn variant(&self) -> Option<V> {
+        V::from_bits(self.bits)
+    }
+}
+
+/// Writer for a single bit field at `OFFSET`
+pub struct BitWriter<'a, S, const OFFSET: u8> {
+    w: &'a mut W<S>,
+}
+
+impl<'a, S, const OFFSET: u8> BitWriter<'a, S, OFFSET> {
+    /// Sets the field to `value`
+    #[inline(always)]
+    pub fn bit(self, value: bool) -> &'a mut W<S> {
+        self.w.bits = (self.w.bits & !(1 << OFFSET)) | ((value as u32) << OFFSET);
+        self.w
+    }
+
+    /// Sets the field to 1
+    #[inline(always)]
+    pub fn set_bit(self) -> &'a mut W<S> {
+        self.bit(true)
+    }
+
+    /// Sets the field to 0
+    #[inline(always)]
+    pub fn clear_bit(self) -> &'a mut W<S> {
+        self.bit(false)
+    }
+}
+
+/// Writer for a `WIDTH` bits field at `OFFSET` with enumerated values `V`
+pub struct FieldWriter<'a, S, const OFFSET: u8, const WIDTH: u8, V = u32> {
+    w: &'a mut W<S>,
+    _value: PhantomData<V>,
+}
+
+impl<'a, S, const OFFSET: u8, const WIDTH: u8, V> FieldWriter<'a, S, OFFSET, WIDTH, V> {
+    /// Sets the field to a raw value, which must fit in `WIDTH` bits
+    #[inline(always)]
+    pub fn bits(self, value: u32) -> &'a mut W<S> {
+        debug_assert!(value <= field_mask(WIDTH), "value doesn't fit the field");
+        let mask = field_mask(WIDTH) << OFFSET;
+        self.w.bits = (self.w.bits & !mask) | ((value << OFFSET) & mask);
+        self.w
+    }
+
+    /// Sets the field to a constant raw value, checked against `WIDTH` at compile time
+    #[inline(always)]
+    pub fn value<const VALUE: u32>(self) -> &'a mut W<S> {
+        const { assert!(VALUE <= field_mask(WIDTH), "value doesn't fit the field") };
+        self.bits(VALUE)
+    }
+}
+
+impl<'a, S, const OFFSET: u8, const WIDTH: u8, V: FieldValue> FieldWriter<'a, S, OFFSET, WIDTH, V> {
+    /// Sets the field to one of its enumerated values
+    #[inline(always)]
+    pub fn variant(self, value: V) -> &'a mut W<S> {
+        const { assert!(V::WIDTH == WIDTH, "enumerated values belong to a field of another width") };
+        self.bits(value.bits())
+    }
+}
+
+/// Defines the layout of a register and its named fields
+///
+/// Fields are `name: bit(OFFSET)`, `name: bits(OFFSET, WIDTH)` or
+/// `name: bits(OFFSET, WIDTH) as Values` for fields with [`field_values!`](crate::field_values)
+#[macro_export]
+macro_rules! register {
+    (
+        $(#[$meta:meta])*
+        $vis:vis $name:ident (reset = $reset:expr) {
+            $(
+                $(#[$fmeta:meta])*
+                $field:ident : $kind:ident ($offset:literal $(, $width:literal)?) $(as $values:ty)?
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name;
+
+        impl $crate::registers::RegisterSpec for $name {
+            const RESET: u32 = $reset;
+        }
+
+        impl $crate::registers::R<$name> {
+            $(
+                $crate::__register_field_reader!($(#[$fmeta])* $field: $kind $offset $(, $width)? $(as $values)?);
+            )*
+        }
+
+        impl $crate::registers::W<$name> {
+            $(
+                $crate::__register_field_writer!($name; $(#[$fmeta])* $field: $kind $offset $(, $width)? $(as $values)?);
+            )*
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_field_reader {
+    ($(#[$fmeta:meta])* $field:ident: bit $offset:literal) => {
+        $(#[$fmeta])*
+        #[inline(always)]
+        pub const fn $field(&self) -> bool {
+            self.bit_at::<$offset>()
+        }
+    };
+    ($(#[$fmeta:meta])* $field:ident: bits $offset:literal, $width:literal) => {
+        $(#[$fmeta])*
+        #[inline(always)]
+        pub const fn $field(&self) -> $crate::registers::FieldReader {
+            self.field_at::<$offset, $width, u32>()
+        }
+    };
+    ($(#[$fmeta:meta])* $field:ident: bits $offset:literal, $width:literal as $values:ty) => {
+        $(#[$fmeta])*
+        #[inline(always)]
+        pub const fn $field(&self) -> $crate::registers::FieldReader<$values> {
+            self.field_at::<$offset, $width, $values>()
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_field_writer {
+    ($name:ident; $(#[$fmeta:meta])* $field:ident: bit $offset:literal) => {
+        $(#[$fmeta])*
+        #[inline(always)]
+        pub fn $field(&mut self) -> $crate::registers::BitWriter<'_, $name, $offset> {
+            self.bit_at::<$offset>()
+        }
+    };
+    ($name:ident; $(#[$fmeta:meta])* $field:ident: bits $offset:literal, $width:literal) => {
+        $(#[$fmeta])*
+        #[inline(always)]
+        pub fn $field(&mut self) -> $crate::registers::FieldWriter<'_, $name, $offset, $width> {
+            self.field_at::<$offset, $width, u32>()
+        }
+    };
+    ($name:ident; $(#[$fmeta:meta])* $field:ident: bits $offset:literal, $width:literal as $values:ty) => {
+        $(#[$fmeta])*
+        #[inline(always)]
+        pub fn $field(&mut self) -> $crate::registers::FieldWriter<'_, $name, $offset, $width, $values> {
+            self.field_at::<$offset, $width, $values>()
+        }
+    };
+}
+
+/// Defines the enumerated values of a register field
+#[macro_export]
+macro_rules! field_values {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident: $width:literal {
+            $( $(#[$vmeta:meta])* $variant:ident = $value:expr ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(u32)]
+        #[allow(non_camel_case_types)]
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        $vis enum $name {
+            $( $(#[$vmeta])* $variant = $value, )*
+        }
+
+        impl $crate::registers::FieldValue for $name {
+            const WIDTH: u8 = $width;
+
+            #[inline(always)]
+            fn bits(self) -> u32 {
+                self as u32
+            }
+
+            #[inline(always)]
+            fn from_bits(bits: u32) -> Option<Self> {
+                $( if bits == $value { return Some(Self::$variant); } )*
+                None
+            }
+        }
+    };
+}
+
+/// Defines a peripheral's register block
+///
+/// Registers are `OFFSET => name: Layout`, or `OFFSET => name[COUNT; STRIDE]: Layout`
+/// for arrays of identical registers indexed at runtime
+#[macro_export]
+macro_rules! register_block {
+    (
+        $(#[$meta:meta])*
+        $vis:vis $name:ident {
+            $(
+                $(#[$rmeta:meta])*
+                $offset:literal => $reg:ident $([$count:literal; $stride:literal])? : $spec:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Copy, Clone, Debug)]
+        $vis struct $name {
+            base: usize,
+        }
+
+        impl $name {
+            /// Creates the register block of the instance at `base`
+            #[inline(always)]
+            pub const fn at(base: usize) -> Self {
+                Self { base }
+            }
+
+            /// Base address of the instance
+            #[inline(always)]
+            pub const fn base(&self) -> usize {
+                self.base
+            }
+
+            $(
+                $crate::__register_block_reg!($(#[$rmeta])* $offset => $reg $([$count; $stride])?: $spec);
+            )*
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_block_reg {
+    ($(#[$rmeta:meta])* $offset:literal => $reg:ident: $spec:ty) => {
+        $(#[$rmeta])*
+        #[inline(always)]
+        pub const fn $reg(&self) -> $crate::registers::Reg<$spec> {
+            $crate::registers::Reg::at(self.base + $offset)
+        }
+    };
+    ($(#[$rmeta:meta])* $offset:literal => $reg:ident [$count:literal; $stride:literal]: $spec:ty) => {
+        $(#[$rmeta])*
+        #[inline(always)]
+        pub const fn $reg(&self, n: usize) -> $crate::registers::Reg<$spec> {
+            assert!(n < $count, "register index out of range");
+            $crate::registers::Reg::at(self.base + $offset + n * $stride)
+        }
+    };
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use crate::backend::sim;
+    use crate::ATOMIC_SET;
+
+    crate::field_values! {
+        enum Mode: 2 {
+            Off = 0,
+            Slow = 1,
+            Fast = 3,
+        }
+    }
+
+    crate::register! {
+        Ctrl (reset = 0x0000_0100) {
+            enabled: bit(31),
+            mode: bits(4, 2) as Mode,
+            count: bits(8, 4),
+            enable: bit(0),
+        }
+    }
+
+    crate::register_block! {
+        Block {
+            0x0 => ctrl: Ctrl,
+            0x10 => slot[4; 0x4]: Ctrl,
+        }
+    }
+
+    const BLOCK: Block = Block::at(0x4001_8000);
+
+    #[test]
+    fn test_write_starts_from_reset_value() {
+        sim::reset();
+        BLOCK.ctrl().write(|w| w.enable().set_bit());
+        assert_eq!(sim::peek(0x4001_8000), 0x101);
+    }
+
+    #[test]
+    fn test_fields() {
+        sim::reset();
+        BLOCK.ctrl().write_with_zero(|w| w.mode().variant(Mode::Fast).count().value::<0xa>());
+        let r = BLOCK.ctrl().read();
+        assert_eq!(r.bits(), 0xa30);
+        assert_eq!(r.mode().variant(), Some(Mode::Fast));
+        assert_eq!(r.count().bits(), 0xa);
+        assert!(!r.enable());
+
+        BLOCK.ctrl().modify(|_, w| w.mode().variant(Mode::Slow).enabled().set_bit());
+        assert_eq!(sim::peek(0x4001_8000), 0x8000_0a10);
+    }
+
+    #[test]
+    fn test_atomic_aliases() {
+        sim::reset();
+        BLOCK.ctrl().set_bits(|w| w.enable().set_bit());
+        assert_eq!(sim::peek(0x4001_8000), 1);
+        BLOCK.ctrl().clear_bits(|w| w.enable().set_bit());
+        assert_eq!(sim::peek(0x4001_8000), 0);
+        BLOCK.ctrl().toggle_bits(|w| w.count().bits(0xf));
+        assert_eq!(sim::peek(0x4001_8000), 0xf00);
+        assert_eq!(sim::peek(0x4001_8000 + ATOMIC_SET), 0);
+    }
+
+    #[test]
+    fn test_register_arrays() {
+        assert_eq!(BLOCK.base(), 0x4001_8000);
+        assert_eq!(BLOCK.slot(0).addr(), 0x4001_8010);
+        assert_eq!(BLOCK.slot(3).addr(), 0x4001_801c);
+    }
+
+    #[test]
+    fn test_unknown_variant() {
+        sim::reset();
+        sim::poke(0x4001_8000, 0x20);
+        assert_eq!(BLOCK.ctrl().read().mode().variant(), None);
+    }
+}
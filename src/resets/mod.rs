@@ -0,0 +1,184 @@
+//! Resets module
+//!
+//! Every peripheral sits behind a line of the reset controller and is unusable while held in reset.
+//! Drivers claim the lines they need with [`acquire`]: the first claim brings the peripheral out
+//! of reset, and it goes back into reset once the last [`ResetHandle`] is dropped. Peripherals
+//! shared by many drivers (IO banks, pads) are therefore never reset under another driver's feet.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::pac::RESETS;
+use crate::spinlocks::Spinlock;
+
+/// Hardware spinlock serializing reset line claims between the cores
+const RESETS_SPINLOCK: usize = 31;
+
+/// Number of reset lines
+pub const RESET_COUNT: usize = 29;
+
+/// RP2350 reset lines, numbered after their bit in the RESET register
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Reset {
+    Adc = 0,
+    Busctrl = 1,
+    Dma = 2,
+    Hstx = 3,
+    I2c0 = 4,
+    I2c1 = 5,
+    IoBank0 = 6,
+    IoQspi = 7,
+    Jtag = 8,
+    PadsBank0 = 9,
+    PadsQspi = 10,
+    Pio0 = 11,
+    Pio1 = 12,
+    Pio2 = 13,
+    PllSys = 14,
+    PllUsb = 15,
+    Pwm = 16,
+    Sha256 = 17,
+    Spi0 = 18,
+    Spi1 = 19,
+    Syscfg = 20,
+    Sysinfo = 21,
+    Tbman = 22,
+    Timer0 = 23,
+    Timer1 = 24,
+    Trng = 25,
+    Uart0 = 26,
+    Uart1 = 27,
+    Usbctrl = 28,
+}
+
+impl Reset {
+    /// Bit of the line in the RESET and RESET_DONE registers
+    #[inline(always)]
+    const fn mask(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// Number of live handles per reset line
+static USERS: [AtomicU8; RESET_COUNT] = [const { AtomicU8::new(0) }; RESET_COUNT];
+
+/// Holds a peripheral in reset
+///
+/// This bypasses the reference counting, prefer dropping the [`ResetHandle`]s
+///
+/// `line`: the peripheral's reset line
+#[inline(always)]
+pub fn reset(line: Reset) {
+    RESETS.reset().set_bits(|w| w.bits(line.mask()));
+}
+
+/// Brings a peripheral out of reset and waits until it's ready
+///
+/// This bypasses the reference counting, prefer [`acquire`]
+///
+/// `line`: the peripheral's reset line
+#[inline(always)]
+pub fn unreset_wait(line: Reset) {
+    RESETS.reset().clear_bits(|w| w.bits(line.mask()));
+    while RESETS.reset_done().read().bits() & line.mask() == 0 {}
+}
+
+/// Whether a peripheral is held in reset
+///
+/// `line`: the peripheral's reset line
+#[inline(always)]
+pub fn is_in_reset(line: Reset) -> bool {
+    RESETS.reset().read().bits() & line.mask() != 0
+}
+
+/// Claims a reset line, bringing the peripheral out of reset if it's held in it
+///
+/// Must not be called from an interrupt handler that can preempt another claim or release,
+/// both take the same hardware spinlock.
+///
+/// `line`: the peripheral's reset line
+pub fn acquire(line: Reset) -> ResetHandle {
+    let _lock = claim_lock();
+    USERS[line as usize].fetch_add(1, Ordering::Relaxed);
+    if is_in_reset(line) {
+        unreset_wait(line);
+    }
+    ResetHandle { line }
+}
+
+/// Spins until the resets spinlock is claimed
+fn claim_lock() -> Spinlock<RESETS_SPINLOCK> {
+    loop {
+        if let Some(lock) = Spinlock::<RESETS_SPINLOCK>::try_claim() {
+            return lock;
+        }
+    }
+}
+
+/// A claim on a reset line, the peripheral stays out of reset while any handle on it is alive
+#[derive(Debug)]
+pub struct ResetHandle {
+    line: Reset,
+}
+
+impl ResetHandle {
+    /// The claimed reset line
+    pub fn line(&self) -> Reset {
+        self.line
+    }
+
+    /// Keeps the peripheral out of reset for the rest of the program
+    pub fn leak(self) {
+        core::mem::forget(self);
+    }
+}
+
+impl Drop for ResetHandle {
+    /// Puts the peripheral back into reset once its last handle is gone
+    fn drop(&mut self) {
+        let _lock = claim_lock();
+        if USERS[self.line as usize].fetch_sub(1, Ordering::Relaxed) == 1 {
+            reset(self.line);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{acquire, is_in_reset, reset, unreset_wait, Reset};
+    use crate::backend::sim;
+    use crate::pac::RESETS;
+
+    #[test]
+    fn test_reset_and_unreset() {
+        sim::reset();
+        assert!(is_in_reset(Reset::Trng));
+        unreset_wait(Reset::Trng);
+        assert!(!is_in_reset(Reset::Trng));
+        assert!(RESETS.reset_done().read().trng());
+        reset(Reset::Trng);
+        assert!(is_in_reset(Reset::Trng));
+        assert!(!RESETS.reset_done().read().trng());
+    }
+
+    #[test]
+    fn test_last_handle_resets() {
+        sim::reset();
+        // No other test claims SHA256, so the counts are this test's own
+        let first = acquire(Reset::Sha256);
+        let second = acquire(Reset::Sha256);
+        assert!(!is_in_reset(Reset::Sha256));
+        drop(first);
+        assert!(!is_in_reset(Reset::Sha256));
+        assert_eq!(second.line(), Reset::Sha256);
+        drop(second);
+        assert!(is_in_reset(Reset::Sha256));
+    }
+
+    #[test]
+    fn test_leaked_handle_keeps_peripheral_running() {
+        sim::reset();
+        acquire(Reset::Tbman).leak();
+        assert!(!is_in_reset(Reset::Tbman));
+    }
+}
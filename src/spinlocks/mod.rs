@@ -1,10 +1,7 @@
-/// Spinlocks module
-///
-mod regs;
+//! Spinlocks module
 
-use crate::{reg_read, reg_write, Valid};
-use crate::gpio::regs::SIO_BASE;
-use crate::spinlocks::regs::*;
+use crate::Valid;
+use crate::pac::SIO;
 
 #[derive(Debug)]
 pub struct Spinlock<const N: usize>(core::marker::PhantomData<()>)
@@ -15,17 +12,13 @@ impl<const N: usize> Spinlock<N>
 where
     Spinlock<N>: Valid {}
 
-fn spinlock_offset(spinlock_num: usize) -> usize {
-    SPINLOCK_BASE_OFFSET + spinlock_num * 4
-}
-
 impl<const N: usize> Spinlock<N>
 where
     Spinlock<N>: Valid,
 {
     /// Try to claim the spinlock
     pub fn try_claim() -> Option<Self> {
-        let claimed: usize = reg_read(SIO_BASE + spinlock_offset(N));
+        let claimed = SIO.spinlock(N).read().bits();
         if claimed > 0 {
             Some(Self(core::marker::PhantomData))
         } else {
@@ -40,7 +33,7 @@ where
     /// caller should not release the lock if they don't own it to begin with.
     /// this would lead to undefined behaviours and race conditions
     pub unsafe fn release() {
-        reg_write(SIO_BASE + spinlock_offset(N), 1);
+        SIO.spinlock(N).write(|w| w.bits(1));
     }
 }
 
@@ -68,3 +61,20 @@ impl_spinlock_valid!(
     16, 17, 18, 19, 20, 21, 22, 23,
     24, 25, 26, 27, 28, 29, 30, 31
 );
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::Spinlock;
+    use crate::backend::sim;
+
+    #[test]
+    fn test_claim_is_exclusive() {
+        sim::reset();
+        let lock = Spinlock::<5>::try_claim();
+        assert!(lock.is_some());
+        assert!(Spinlock::<5>::try_claim().is_none());
+        assert!(Spinlock::<6>::try_claim().is_some());
+        drop(lock);
+        assert!(Spinlock::<5>::try_claim().is_some());
+    }
+}
@@ -1,4 +0,0 @@
-//! Register addresses for the Spinlocks module
-
-pub const SPINLOCK_BASE_OFFSET: usize = 0x100;
-
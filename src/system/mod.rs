@@ -0,0 +1,333 @@
+//! System initialisation
+//!
+//! [`SystemConfig`] describes the crystal, the clocks and the peripherals to bring up, checks
+//! that they fit together and runs the boot sequence, returning a [`System`] token that records
+//! what was initialised.
+//!
+//! ```ignore
+//! let system = unsafe { SystemConfig::new().sys_hz(200_000_000).uart0(115200).init() }?;
+//! ```
+
+use crate::clocks::pll::{self, Pll, PllSolution};
+use crate::clocks::xosc::{self, STARTUP_DELAY_MAX, XOSC_MAX_HZ, XOSC_MIN_HZ};
+use crate::clocks::{self, Clock, ClockError, Source, XOSC_HZ, XOSC_STARTUP_MS};
+use crate::interrupts::copy_vector_table_to_ram;
+use crate::pac::xosc::CtrlFreqRange;
+use crate::timers::start_timers;
+use crate::{reset_peripherals, uart};
+
+/// USB needs exactly 48 MHz
+const USB_HZ: usize = 48_000_000;
+/// Default clk_sys frequency
+const SYS_HZ: usize = 150_000_000;
+
+/// Reasons a [`SystemConfig`] can't be applied
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The crystal isn't a whole number of MHz within 1-50 MHz, the timers need whole MHz
+    Crystal,
+    /// The XOSC frequency range doesn't cover the crystal
+    XoscRange,
+    /// The startup time doesn't fit XOSC STARTUP.DELAY at this crystal frequency
+    StartupDelay,
+    /// PLL_SYS can't reach the requested clk_sys
+    SysClock(ClockError),
+    /// PLL_USB can't make exactly 48 MHz out of the crystal
+    UsbClock,
+    /// The baud rate is out of the UART divider's range
+    Baud,
+    /// A clock generator refused its source or divider while booting, the clocks are left as
+    /// far as the boot sequence got
+    ClockSwitch(Clock, ClockError),
+}
+
+/// Boot configuration, see the module documentation
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SystemConfig {
+    xosc_hz: usize,
+    xosc_range: Option<CtrlFreqRange>,
+    xosc_startup_ms: u32,
+    sys_hz: usize,
+    usb_clocks: bool,
+    timers: bool,
+    uart0_baud: Option<usize>,
+    relocate_vectors: bool,
+}
+
+impl Default for SystemConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SystemConfig {
+    /// The default boot: 12 MHz crystal settling for 10 ms, 150 MHz clk_sys, 48 MHz USB and ADC clocks, timers
+    /// running, vector table in RAM and no UART
+    pub const fn new() -> Self {
+        Self {
+            xosc_hz: XOSC_HZ,
+            xosc_range: None,
+            xosc_startup_ms: XOSC_STARTUP_MS,
+            sys_hz: SYS_HZ,
+            usb_clocks: true,
+            timers: true,
+            uart0_baud: None,
+            relocate_vectors: true,
+        }
+    }
+
+    /// Sets the crystal frequency
+    ///
+    /// `hz`: crystal frequency in Hz
+    pub const fn xosc_hz(mut self, hz: usize) -> Self {
+        self.xosc_hz = hz;
+        self
+    }
+
+    /// Overrides the XOSC frequency range, picked from the crystal frequency by default
+    ///
+    /// `range`: the XOSC frequency range
+    pub const fn xosc_range(mut self, range: CtrlFreqRange) -> Self {
+        self.xosc_range = Some(range);
+        self
+    }
+
+    /// Sets how long the crystal gets to settle
+    ///
+    /// `ms`: startup time in milliseconds
+    pub const fn xosc_startup_ms(mut self, ms: u32) -> Self {
+        self.xosc_startup_ms = ms;
+        self
+    }
+
+    /// Sets the target clk_sys frequency, PLL_SYS gets as close as it can
+    ///
+    /// `hz`: clk_sys frequency in Hz
+    pub const fn sys_hz(mut self, hz: usize) -> Self {
+        self.sys_hz = hz;
+        self
+    }
+
+    /// Whether to bring up PLL_USB and run clk_usb and clk_adc from it
+    ///
+    /// `enable`: true to bring them up
+    pub const fn usb_clocks(mut self, enable: bool) -> Self {
+        self.usb_clocks = enable;
+        self
+    }
+
+    /// Whether to start the timers
+    ///
+    /// `enable`: true to start them
+    pub const fn timers(mut self, enable: bool) -> Self {
+        self.timers = enable;
+        self
+    }
+
+    /// Brings up UART0 on GPIO0/GPIO1
+    ///
+    /// `baud`: the UART baudrate
+    pub const fn uart0(mut self, baud: usize) -> Self {
+        self.uart0_baud = Some(baud);
+        self
+    }
+
+    /// Whether to copy the vector table to RAM, needed by [`crate::interrupts::set_irq_handler`]
+    ///
+    /// `enable`: true to relocate the vector table
+    pub const fn relocate_vectors(mut self, enable: bool) -> Self {
+        self.relocate_vectors = enable;
+        self
+    }
+
+    /// Checks the configuration and solves the PLLs
+    pub fn validate(&self) -> Result<Plan, ConfigError> {
+        if !(XOSC_MIN_HZ..=XOSC_MAX_HZ).contains(&self.xosc_hz) || !self.xosc_hz.is_multiple_of(1_000_000) {
+            return Err(ConfigError::Crystal);
+        }
+        let xosc_range = match self.xosc_range {
+            Some(range) if xosc::range_covers(range, self.xosc_hz) => range,
+            Some(_) => return Err(ConfigError::XoscRange),
+            None => xosc::range_for(self.xosc_hz).ok_or(ConfigError::XoscRange)?,
+        };
+        let startup_delay = xosc::startup_delay(self.xosc_hz, self.xosc_startup_ms);
+        if startup_delay > STARTUP_DELAY_MAX {
+            return Err(ConfigError::StartupDelay);
+        }
+        let sys = pll::solve(self.xosc_hz, self.sys_hz).map_err(ConfigError::SysClock)?;
+        let usb = if self.usb_clocks {
+            match pll::solve(self.xosc_hz, USB_HZ) {
+                Ok(usb) if usb.error_hz == 0 => Some(usb),
+                _ => return Err(ConfigError::UsbClock),
+            }
+        } else {
+            None
+        };
+        if let Some(baud) = self.uart0_baud {
+            // clk_peri runs from the crystal, the divider is 16 x 16.6 fixed point
+            if baud == 0 || 16 * baud > self.xosc_hz || self.xosc_hz / (16 * baud) > 0xffff {
+                return Err(ConfigError::Baud);
+            }
+        }
+        Ok(Plan { config: *self, xosc_range, startup_delay, sys, usb })
+    }
+
+    /// Validates the configuration and boots with it
+    ///
+    /// # Safety
+    ///
+    /// Must be called once, before anything else touches the clocks or peripherals
+    pub unsafe fn init(self) -> Result<System, ConfigError> {
+        self.validate()?.apply()
+    }
+}
+
+/// A validated [`SystemConfig`] with its PLL settings
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Plan {
+    config: SystemConfig,
+    xosc_range: CtrlFreqRange,
+    startup_delay: u32,
+    sys: PllSolution,
+    usb: Option<PllSolution>,
+}
+
+impl Plan {
+    /// PLL_SYS settings and the clk_sys frequency they achieve
+    pub fn sys(&self) -> &PllSolution {
+        &self.sys
+    }
+
+    /// Runs the boot sequence
+    ///
+    /// # Safety
+    ///
+    /// Same as [`SystemConfig::init`]
+    pub unsafe fn apply(self) -> Result<System, ConfigError> {
+        let config = &self.config;
+        let switch = |clock, source, src_hz, hz| {
+            clocks::configure(clock, source, src_hz, hz).map_err(|err| ConfigError::ClockSwitch(clock, err))
+        };
+        reset_peripherals();
+
+        xosc::init(config.xosc_hz, self.xosc_range, self.startup_delay);
+        switch(Clock::Ref, Source::Xosc, config.xosc_hz, config.xosc_hz)?;
+
+        pll::init(Pll::Sys, &self.sys.config);
+        switch(Clock::Sys, Source::PllSys, self.sys.hz, self.sys.hz)?;
+        switch(Clock::Peri, Source::Xosc, config.xosc_hz, config.xosc_hz)?;
+
+        if let Some(usb) = self.usb {
+            pll::init(Pll::Usb, &usb.config);
+            switch(Clock::Usb, Source::PllUsb, usb.hz, USB_HZ)?;
+            switch(Clock::Adc, Source::PllUsb, usb.hz, USB_HZ)?;
+        }
+
+        if config.timers {
+            start_timers();
+        }
+        if config.relocate_vectors {
+            copy_vector_table_to_ram();
+        }
+        // The console is left down if GPIO0 or GPIO1 is already taken
+        let uart0_baud = config.uart0_baud.filter(|&baud| uart::uart_init(baud).is_ok());
+
+        Ok(System {
+            sys_hz: self.sys.hz,
+            usb_clocks: self.usb.is_some(),
+            timers: config.timers,
+            uart0_baud,
+            vectors_in_ram: config.relocate_vectors,
+        })
+    }
+}
+
+/// Token of an initialised system, records which peripherals are up
+#[derive(Debug, PartialEq, Eq)]
+pub struct System {
+    sys_hz: usize,
+    usb_clocks: bool,
+    timers: bool,
+    uart0_baud: Option<usize>,
+    vectors_in_ram: bool,
+}
+
+impl System {
+    /// clk_sys frequency achieved, in Hz
+    pub fn sys_hz(&self) -> usize {
+        self.sys_hz
+    }
+
+    /// Whether clk_usb and clk_adc run at 48 MHz
+    pub fn usb_clocks(&self) -> bool {
+        self.usb_clocks
+    }
+
+    /// Whether the timers are running
+    pub fn timers(&self) -> bool {
+        self.timers
+    }
+
+    /// UART0's baudrate if it was brought up, `None` as well when its pins were taken
+    pub fn uart0_baud(&self) -> Option<usize> {
+        self.uart0_baud
+    }
+
+    /// Whether the vector table was copied to RAM
+    pub fn vectors_in_ram(&self) -> bool {
+        self.vectors_in_ram
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{ConfigError, SystemConfig};
+    use crate::backend::sim;
+    use crate::clocks::{clock_get_hz, Clock, ClockError};
+    use crate::pac::xosc::CtrlFreqRange;
+    use crate::pac::{UART0, XOSC};
+
+    #[test]
+    fn test_validate_rejects_bad_combinations() {
+        let config = SystemConfig::new();
+        assert_eq!(config.xosc_hz(12_500_000).validate(), Err(ConfigError::Crystal));
+        assert_eq!(config.xosc_hz(60_000_000).validate(), Err(ConfigError::Crystal));
+        assert_eq!(
+            config.xosc_hz(20_000_000).xosc_range(CtrlFreqRange::_1_15mhz).validate(),
+            Err(ConfigError::XoscRange)
+        );
+        assert_eq!(config.xosc_startup_ms(400).validate(), Err(ConfigError::StartupDelay));
+        assert_eq!(
+            config.sys_hz(2_000_000_000).validate(),
+            Err(ConfigError::SysClock(ClockError::PllOutOfRange))
+        );
+        assert_eq!(config.uart0(1_000_000).validate(), Err(ConfigError::Baud));
+    }
+
+    #[test]
+    fn test_validate_other_crystals() {
+        let plan = SystemConfig::new().xosc_hz(10_000_000).sys_hz(200_000_000).validate().unwrap();
+        assert_eq!(plan.sys().hz, 200_000_000);
+        // 48 MHz isn't reachable exactly from 13 MHz, unless USB is left out
+        let config = SystemConfig::new().xosc_hz(13_000_000);
+        assert_eq!(config.validate(), Err(ConfigError::UsbClock));
+        assert!(config.usb_clocks(false).validate().is_ok());
+        // A 24 MHz crystal moves to the 10-30 MHz range
+        assert!(SystemConfig::new().xosc_hz(24_000_000).validate().is_ok());
+    }
+
+    #[test]
+    fn test_init_brings_up_requested_peripherals() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        XOSC.status().write(|w| w.stable().set_bit());
+        let system = unsafe { SystemConfig::new().relocate_vectors(false).uart0(115200).init() }.unwrap();
+        assert_eq!(system.sys_hz(), 150_000_000);
+        assert_eq!(system.uart0_baud(), Some(115200));
+        assert!(system.usb_clocks() && system.timers() && !system.vectors_in_ram());
+        assert_eq!(clock_get_hz(Clock::Usb), 48_000_000);
+        assert!(UART0.uartcr().read().uarten());
+        assert_eq!(sim::peek(XOSC.ctrl().addr()), 0xfabaa0);
+        assert_eq!(XOSC.startup().read().delay().bits(), 469);
+    }
+}
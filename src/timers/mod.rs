@@ -1,48 +1,103 @@
 /// Timers modules
-mod regs;
-
-use crate::timers::regs::*;
-use crate::{reg_write, ATOMIC_SET};
+use crate::clocks::{self, clock_get_hz, Clock};
+use crate::pac::{TICKS, TIMER0};
 
 /// Enable timers
 ///
 /// # Safety
 ///
-/// caller must ensure timers are not already enabled
+/// caller must ensure timers are not already enabled and clk_ref is configured
+/// to a whole number of MHz
 pub unsafe fn start_timers() {
     // Reset TIMER0 counter
-    reg_write(TIMER0_TIMERAWL, 0);
-    reg_write(TIMER0_TIMERAWH, 0);
+    TIMER0.timerawl().write(|w| w);
+    TIMER0.timerawh().write(|w| w);
 
-    // Set timer0 cycles small offset
-    reg_write(TICKS_TIMER0_CYCLES, 12);
+    set_tick_source_hz(clock_get_hz(Clock::Ref));
+    // Keep ticking at 1 MHz when clk_ref changes
+    let _ = clocks::add_listener(Clock::Ref, on_clk_ref_change);
 
     // Enable timer0
-    reg_write(TICKS_TIMER0_CTRL + ATOMIC_SET, 1);
+    TICKS.timer_ctrl(0).set_bits(|w| w.enable().set_bit());
+}
+
+/// One tick per microsecond: count as many clk_ref cycles as it has per microsecond
+///
+/// `clk_ref_hz`: the reference clock frequency in Hz
+fn set_tick_source_hz(clk_ref_hz: usize) {
+    let cycles = clk_ref_hz / 1_000_000;
+    TICKS.timer_cycles(0).write(|w| w.timer_cycles().bits(cycles as u32));
+}
+
+/// Reprograms the tick generator for the new clk_ref
+///
+/// `hz`: the new clk_ref frequency in Hz
+fn on_clk_ref_change(_clock: Clock, hz: usize) {
+    if hz >= 1_000_000 {
+        set_tick_source_hz(hz);
+    }
 }
 
 /// Busy wait for given milliseconds
 ///
 /// `ms`: milliseconds to wait
 pub fn wait_ms(ms: u32) {
-    unsafe {
-        // Read starting value
-        let high_start = core::ptr::read_volatile(TIMER0_TIMEHR as *const u32);
-        let low_start = core::ptr::read_volatile(TIMER0_TIMELR as *const u32);
-        let start: u64 = ((high_start as u64) << 32) | (low_start as u64);
-
-        // Compute target timestamp
-        let target: u64 = start + (ms as u64 * 1000);
-
-        // Busy wait
-        loop {
-            let high = core::ptr::read_volatile(TIMER0_TIMERAWH as *const u32);
-            let low = core::ptr::read_volatile(TIMER0_TIMERAWL as *const u32);
-            let now: u64 = ((high as u64) << 32) | (low as u64);
-
-            if now >= target {
-                break;
-            }
+    // Read starting value
+    let high_start = TIMER0.timehr().read().bits();
+    let low_start = TIMER0.timelr().read().bits();
+    let start: u64 = ((high_start as u64) << 32) | (low_start as u64);
+
+    // Compute target timestamp
+    let target: u64 = start + (ms as u64 * 1000);
+
+    // Busy wait
+    loop {
+        let high = TIMER0.timerawh().read().bits();
+        let low = TIMER0.timerawl().read().bits();
+        let now: u64 = ((high as u64) << 32) | (low as u64);
+
+        if now >= target {
+            break;
         }
     }
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use super::{on_clk_ref_change, start_timers, wait_ms};
+    use crate::backend::sim;
+    use crate::clocks::{clock_set_reported_hz, Clock, XOSC_HZ};
+    use crate::pac::{TICKS, TIMER0};
+
+    #[test]
+    fn test_start_timers() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        clock_set_reported_hz(Clock::Ref, XOSC_HZ);
+        unsafe { start_timers() };
+        assert_eq!(TICKS.timer_cycles(0).read().timer_cycles().bits(), 12);
+        assert!(TICKS.timer_ctrl(0).read().enable());
+    }
+
+    #[test]
+    fn test_ticks_follow_clk_ref() {
+        sim::reset();
+        on_clk_ref_change(Clock::Ref, 24_000_000);
+        assert_eq!(TICKS.timer_cycles(0).read().timer_cycles().bits(), 24);
+        // Too slow for microsecond ticks, keep the previous setting
+        on_clk_ref_change(Clock::Ref, 32_768);
+        assert_eq!(TICKS.timer_cycles(0).read().timer_cycles().bits(), 24);
+    }
+
+    #[test]
+    fn test_wait_ms_returns_after_deadline() {
+        sim::reset();
+        // Every poll of the raw low word advances time by 100us
+        sim::on_read(TIMER0.timerawl().addr(), |addr, stored| {
+            sim::poke(addr, stored + 100);
+            stored
+        });
+        wait_ms(5);
+        assert!(TIMER0.timerawl().read().bits() >= 5_000);
+    }
+}
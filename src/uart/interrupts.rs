@@ -1,15 +1,13 @@
 //! UART Interrupt controller
 
-use crate::uart::regs::*;
+use crate::pac::UART0;
 
 /// a default interrupt handler for the UART stack
 pub fn uart_handle_rx_irq() {
     // Drain RX FIFO
-    unsafe {
-        while (core::ptr::read_volatile((UART0_BASE + UARTFR_OFFSET) as *const usize) & UARTFR_RXFE) == 0 {
-            let ch = core::ptr::read_volatile((UART0_BASE + UARTDR_OFFSET) as *const u32) as u8;
-            let _ = ch;
-        }
+    while !UART0.uartfr().read().rxfe() {
+        let ch = UART0.uartdr().read().data().bits() as u8;
+        let _ = ch;
     }
 }
 
@@ -18,24 +16,15 @@ pub fn uart_handle_rx_irq() {
 /// `rx = true`: enable RX interrupts
 /// `tx = true`: enable TX interrupts
 pub fn uart_irq_enable(rx: bool, tx: bool) {
+    // Clear pending & enable
+    UART0.uarticr().write(|w| w.bits(0x7FF));
+    UART0.uartimsc().write_with_zero(|w| w.rxim().bit(rx).txim().bit(tx));
 
-    let mut mask = 0;
-    if rx { mask |= 1 << 4; } // RXIM
-    if tx { mask |= 1 << 5; } // TXIM
-
-    unsafe {
-        // Clear pending & enable
-        core::ptr::write_volatile((UART0_BASE + UARTICR_OFFSET) as *mut u32, 0x7FF);
-        core::ptr::write_volatile((UART0_BASE + UARTIMSC_OFFSET) as *mut u32, mask);
-
-        // Dummy read
-        core::ptr::read_volatile((UART0_BASE + UARTIMSC_OFFSET) as *const u32);
-    }
+    // Dummy read
+    UART0.uartimsc().read();
 }
 
 /// Clears the interrupt flag for UART RX interrupt
 pub fn clear_rx_irq() {
-    unsafe {
-        core::ptr::write_volatile((UART0_BASE + UARTICR_OFFSET) as *mut usize, RXIC);
-    }
+    UART0.uarticr().write(|w| w.rxic().set_bit());
 }
@@ -1,13 +1,15 @@
 //! Uart module
 
-mod regs;
 pub mod interrupts;
 
-use crate::clocks::{clock_get_hz};
-use crate::gpio::{gpio_ctrl_offset, gpio_pad_offset};
-use crate::{register, ATOMIC_CLEAR};
-use regs::*;
-use crate::clocks::Clock::Ref;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::clocks::{self, clock_get_hz, Clock};
+use crate::gpio::pad::{Pad, PadConfig};
+use crate::gpio::{FunctionPin, Funcsel, Pull};
+use crate::pac::uart::UartlcrHWlen;
+use crate::pac::{IO_BANK0, UART0};
+use crate::resets::{self, Reset};
 
 // -------- helpers ----------
 
@@ -26,130 +28,118 @@ fn baud_divisors(clk_peri_hz: usize, baud: usize) -> (usize, usize) {
     (ibrd, fbrd)
 }
 
-/// Return the current clk_peri in Hz (assumes AUXSRC = XOSC and DIV INT field)
-fn get_clk_peri_hz() -> usize {
-    unsafe {
-        let ctrl = core::ptr::read_volatile(register(CLOCKS_BASE + CLK_PERI_CTRL_OFFSET));
-        let enabled = (ctrl >> 28) & 1; // ENABLED (read-only)
-        if enabled == 0 { return 0; }
-        let auxsrc = (ctrl >> 5) & 0x7; // AUXSRC
-
-        // set AUXSRC to XOSC (0x4), 12 MHz:
-        let base = if auxsrc == 0x4 { clock_get_hz(Ref) } else { 0 }; // known path
-        let div = core::ptr::read_volatile(register(CLOCKS_BASE + CLK_PERI_DIV_OFFSET));
-        let int_div = (div >> 16) & 0x3; // INT bits [17:16]
-        let int_div = if int_div == 0 { 1 } else { int_div }; // 0 means max+1; we won’t use it
-        base / int_div
+/// Baudrate UART0 was set to, 0 before [`uart_init`]
+static UART0_BAUD: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether [`uart_init`] holds GPIO0 and GPIO1 in the pin registry
+static CONSOLE_PINS: AtomicBool = AtomicBool::new(false);
+
+/// Errors reported by [`uart_init`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UartError {
+    /// GPIO0 or GPIO1 is already taken by someone else
+    PinsTaken,
+}
+
+/// Programs the baud rate divisors for a clk_peri frequency
+///
+/// `clk_peri_hz`: the peripheral clock frequency in Hz
+/// `baud`: the UART baudrate
+fn apply_baud(clk_peri_hz: usize, baud: usize) {
+    let (ibrd, fbrd) = baud_divisors(clk_peri_hz, baud);
+    UART0.uartibrd().write(|w| w.baud_divint().bits(ibrd as u32));
+    UART0.uartfbrd().write(|w| w.baud_divfrac().bits(fbrd as u32));
+
+    // Latch divisors by writing LCR_H (any write latches IBRD/FBRD)
+    UART0.uartlcr_h().modify(|_, w| w);
+}
+
+/// Keeps the baud rate when clk_peri changes
+///
+/// `hz`: the new clk_peri frequency in Hz
+fn on_clk_peri_change(_clock: Clock, hz: usize) {
+    let baud = UART0_BAUD.load(Ordering::Relaxed);
+    if baud != 0 && hz != 0 {
+        apply_baud(hz, baud);
     }
 }
 
+/// Changes UART0's baud rate
+///
+/// `baud`: the new baudrate
+pub fn uart_set_baud(baud: usize) {
+    UART0_BAUD.store(baud, Ordering::Relaxed);
+    apply_baud(clock_get_hz(Clock::Peri), baud);
+}
+
 /// Initializes the UART controller with default UART0 GPIOs
 ///
+/// The baud rate follows clk_peri changes from then on. GPIO0 and GPIO1 stay taken for good,
+/// fails with [`UartError::PinsTaken`] if someone else holds either of them.
+///
 /// # Safety
 ///
-/// the caller must ensure that the system clocks are initialized
+/// the caller must ensure that clk_peri is configured
 /// `baud`: the baudrate value to sync UART
-pub unsafe fn uart_init(baud: usize) {
-    // 1) clk_peri: AUXSRC = XOSC (0x4), DIV = 1, ENABLE = 1
-    let clk_ctrl = register(CLOCKS_BASE + CLK_PERI_CTRL_OFFSET);
-    let clk_div  = register(CLOCKS_BASE + CLK_PERI_DIV_OFFSET);
-
-    // Clean stop
-    let mut ctrl = core::ptr::read_volatile(clk_ctrl);
-    ctrl &= !(1 << 11);                  // ENABLE = 0
-    core::ptr::write_volatile(clk_ctrl, ctrl);
-
-    // Divider = 1 (INT=1 at bits [17:16])
-    // (RESET says INT default is 0x1 already)
-    let div_val = 1usize << 16;
-    core::ptr::write_volatile(clk_div, div_val);
-
-    // AUXSRC = 0x4 (XOSC_CLKSRC), ENABLE=1
-    ctrl &= !(0x7 << 5);
-    ctrl |= 0x4 << 5; // XOSC
-    ctrl |= 1 << 11;  // ENABLE
-    core::ptr::write_volatile(clk_ctrl, ctrl);
-    // (ENABLED RO bit lives at 28)
-
-    // 2) Release UART0 from reset
-    let resets_clr = register(RESETS_BASE + RESETS_RESET_OFFSET + ATOMIC_CLEAR);
-    core::ptr::write_volatile(resets_clr, RESET_UART0_BIT);
-    let reset_done = register(RESETS_BASE + RESETS_RESET_DONE_OFFSET);
-    while (core::ptr::read_volatile(reset_done) & RESET_UART0_BIT) == 0 {}
-
-    // 3) IO mux: GPIO0 = UART0_RX, GPIO1 = UART0_TX
-    // FUNCSEL = 0x2 for both pins, per IO_BANK0 tables
-    let gpio0_ctrl = register(IO_BANK0_BASE + gpio_ctrl_offset(0));
-    let mut v0 = core::ptr::read_volatile(gpio0_ctrl);
-    v0 = (v0 & !0x1F) | 0x02; // FUNCSEL 0x2 = UART0_TX
-    core::ptr::write_volatile(gpio0_ctrl, v0);
-
-    let gpio1_ctrl = register(IO_BANK0_BASE + gpio_ctrl_offset(1));
-    let mut v1 = core::ptr::read_volatile(gpio1_ctrl);
-    v1 = (v1 & !0x1F) | 0x02; // FUNCSEL 0x2 = UART0_RX
-    core::ptr::write_volatile(gpio1_ctrl, v1);
-
-    // Pads: RX (GPIO0) needs IE + PUE; TX (GPIO1) no pulls, output enabled (OD=0), de-isolate
-    // TX pin (GPIO0) - output, no pulls
-    let gpio0_pad = register(PADS_BANK0_BASE + gpio_pad_offset(0));
-    let mut p0 = core::ptr::read_volatile(gpio0_pad);
-    p0 &= !(PADS_IO_PUE | PADS_IO_PDE | PADS_IO_ISO);
-    core::ptr::write_volatile(gpio0_pad, p0);
-
-    // RX pin (GPIO1) - input + pull-up
-    let gpio1_pad = register(PADS_BANK0_BASE + gpio_pad_offset(1));
-    let mut p1 = core::ptr::read_volatile(gpio1_pad);
-    p1 |= PADS_IO_IE | PADS_IO_PUE;
-    p1 &= !(PADS_IO_PDE | PADS_IO_ISO);
-    core::ptr::write_volatile(gpio1_pad, p1);
-
-    // 4) UART registers
+pub unsafe fn uart_init(baud: usize) -> Result<(), UartError> {
+    // 1) Release UART0 and the IO bank it's muxed through from reset, for good
+    resets::acquire(Reset::Uart0).leak();
+    resets::acquire(Reset::IoBank0).leak();
+    resets::acquire(Reset::PadsBank0).leak();
+
+    // 2) IO mux: GPIO0 = UART0_TX, GPIO1 = UART0_RX, claimed once and never given back
+    if CONSOLE_PINS.load(Ordering::Relaxed) {
+        for pin in [0, 1] {
+            IO_BANK0.gpio_ctrl(pin).modify(|_, w| w.funcsel().bits(Funcsel::Uart as u32));
+        }
+    } else {
+        let tx = FunctionPin::claim(0, Funcsel::Uart).ok_or(UartError::PinsTaken)?;
+        let rx = FunctionPin::claim(1, Funcsel::Uart).ok_or(UartError::PinsTaken)?;
+        core::mem::forget((tx, rx));
+        CONSOLE_PINS.store(true, Ordering::Relaxed);
+    }
+
+    // Pads: TX (GPIO0) no pulls, RX (GPIO1) pulled up so an unplugged line reads idle
+    for (pin, pull) in [(0, Pull::None), (1, Pull::Up)] {
+        let pad = Pad::new(pin);
+        pad.configure(&PadConfig::new().pull(pull));
+        pad.set_isolated(false);
+    }
+
+    // 3) UART registers
     // Disable while configuring
-    core::ptr::write_volatile(register(UART0_BASE + UARTCR_OFFSET), 0);
+    UART0.uartcr().write_with_zero(|w| w);
 
     // Clear all interrupts/errors
-    core::ptr::write_volatile(register(UART0_BASE + UARTICR_OFFSET), 0x7FF);
+    UART0.uarticr().write(|w| w.bits(0x7FF));
 
-    // Choose FIFO trigger levels ~1/2
-    // RXIFLSEL [5:3], TXIFLSEL [2:0]: 0b010 = 1/4, 0b011 = 1/2.
-    let ifls = (0b011 << 3) | 0b011;
-    core::ptr::write_volatile(register(UART0_BASE + UARTIFLS_OFFSET), ifls);
+    // Choose FIFO trigger levels, 0b011 = 3/4 full
+    UART0.uartifls().write(|w| w.rxiflsel().value::<0b011>().txiflsel().value::<0b011>());
 
     // Mask all interrupts
-    core::ptr::write_volatile(register(UART0_BASE + UARTIMSC_OFFSET), 0);
+    UART0.uartimsc().write_with_zero(|w| w);
 
-    // Compute divisors for 115200 using the actual clk_peri we just set
-    let clk_peri = get_clk_peri_hz(); // should be 12_000_000
-    let (ibrd, fbrd) = baud_divisors(clk_peri, baud);
-    core::ptr::write_volatile(register(UART0_BASE + UARTIBRD_OFFSET), ibrd & 0xFFFF);
-    core::ptr::write_volatile(register(UART0_BASE + UARTFBRD_OFFSET), fbrd & 0x3F);
+    // Compute divisors from the configured clk_peri, and again whenever it changes
+    uart_set_baud(baud);
+    let _ = clocks::add_listener(Clock::Peri, on_clk_peri_change);
 
-    // Latch divisors by writing LCR_H (any write latches IBRD/FBRD)
-    let lcr_h_val = UARTLCR_H_WLEN_8 | UARTLCR_H_FEN; // 8N1 + FIFO
-    // (Write a dummy first to ensure latch, matching SDK behavior)
-    let lcr_save = core::ptr::read_volatile(register(UART0_BASE + UARTLCR_H_OFFSET));
-    core::ptr::write_volatile(register(UART0_BASE + UARTLCR_H_OFFSET), lcr_save);
-    // Now set desired format
-    core::ptr::write_volatile(register(UART0_BASE + UARTLCR_H_OFFSET), lcr_h_val);
+    // Now set desired format, 8N1 + FIFO
+    UART0.uartlcr_h().write(|w| w.wlen().variant(UartlcrHWlen::_8bits).fen().set_bit());
 
     // DMA off
-    core::ptr::write_volatile(register(UART0_BASE + UARTDMACR_OFFSET), 0);
+    UART0.uartdmacr().write(|w| w);
 
     // Enable UART, TX, RX
-    core::ptr::write_volatile(
-        register(UART0_BASE + UARTCR_OFFSET),
-        UARTCR_UARTEN | UARTCR_TXE | UARTCR_RXE,
-    );
+    UART0.uartcr().write_with_zero(|w| w.uarten().set_bit().txe().set_bit().rxe().set_bit());
+    Ok(())
 }
 
 /// Blocking putc
 ///
 /// `b`: byte to write to the uart buffer
 pub fn putc(b: u8) {
-    unsafe {
-        while (core::ptr::read_volatile(register(UART0_BASE + UARTFR_OFFSET)) & UARTFR_TXFF) != 0 {}
-        core::ptr::write_volatile(register(UART0_BASE + UARTDR_OFFSET), b as usize);
-    }
+    while UART0.uartfr().read().txff() {}
+    UART0.uartdr().write(|w| w.data().bits(b as u32));
 }
 
 /// Blocking puts
@@ -163,32 +153,27 @@ pub fn puts(s: &str) {
 
 /// Blocking get_char: waits until a character is available, then returns it
 pub fn getc() -> char {
-    unsafe {
-        // 1. Wait until the receive FIFO is not empty (RXFE == 0).
-        while (core::ptr::read_volatile(register(UART0_BASE + UARTFR_OFFSET)) & UARTFR_RXFE) != 0 {
-            // Busy-wait: do nothing until a character arrives.
-        }
-
-        // 2. Read the data register to get the received byte.
-        // Bits [7:0] of UARTDR hold the character data.
-        let data = core::ptr::read_volatile(register(UART0_BASE + UARTDR_OFFSET));
-        // 3. Return the lowest 8 bits as the character (ignore any error flags in upper bits).
-        (data as u8) as char
+    // 1. Wait until the receive FIFO is not empty (RXFE == 0).
+    while UART0.uartfr().read().rxfe() {
+        // Busy-wait: do nothing until a character arrives.
     }
+
+    // 2. Read the data register to get the received byte.
+    // Bits [7:0] of UARTDR hold the character data.
+    let data = UART0.uartdr().read().data().bits();
+    // 3. Return the character (ignore any error flags in upper bits).
+    (data as u8) as char
 }
 
 /// Non-blocking get_char: returns `Option<u8>`
 /// - Some(byte) if a character is ready
 /// - None if FIFO empty
 pub fn getc_nonblocking() -> Option<u8> {
-    unsafe {
-        let fr = core::ptr::read_volatile(register(UART0_BASE + UARTFR_OFFSET));
-        if (fr & UARTFR_RXFE) != 0 {
-            None
-        } else {
-            let data = core::ptr::read_volatile(register(UART0_BASE + UARTDR_OFFSET));
-            Some((data & 0xFF) as u8)  // strip error bits
-        }
+    if UART0.uartfr().read().rxfe() {
+        None
+    } else {
+        let data = UART0.uartdr().read().data().bits();
+        Some(data as u8)  // error bits stripped
     }
 }
 
@@ -199,7 +184,7 @@ pub fn getc_nonblocking() -> Option<u8> {
 /// This function drops all pending data on the FIFO
 pub unsafe fn uart_flush() {
     // Wait until TX FIFO is empty
-    while (core::ptr::read_volatile(register(UART0_BASE + UARTFR_OFFSET)) & (1 << 3)) != 0 {}
+    while UART0.uartfr().read().busy() {}
 }
 
 /// Enable or disable the UART FIFO (RX/TX FIFOs).
@@ -207,21 +192,10 @@ pub unsafe fn uart_flush() {
 /// `enabled = true`: enables FIFO mode (default in uart_init)
 /// `enabled = false`: disables FIFO (for character-by-character interrupts)
 pub fn uart_enable_fifo(enabled: bool) {
-    let lcr_h_addr = (UART0_BASE + UARTLCR_H_OFFSET) as *mut usize;
-    unsafe {
-        let mut lcr_h_val = core::ptr::read_volatile(lcr_h_addr);
-
-        if enabled {
-            lcr_h_val |= UARTLCR_H_FEN;  // set bit
-        } else {
-            lcr_h_val &= !UARTLCR_H_FEN; // clear bit
-        }
-
-        core::ptr::write_volatile(lcr_h_addr, lcr_h_val);
-    }
+    UART0.uartlcr_h().modify(|_, w| w.fen().bit(enabled));
 }
 
-#[cfg(test)]
+#[cfg(all(test, target_os = "none"))]
 mod tests {
     use super::putc;
     use super::puts;
@@ -234,3 +208,101 @@ mod tests {
         puts("\r\n");
     }
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests {
+    use core::sync::atomic::Ordering;
+
+    use super::{baud_divisors, on_clk_peri_change, putc, uart_init, UartError, CONSOLE_PINS};
+    use crate::backend::sim;
+    use crate::clocks::{clock_get_hz, clock_set_reported_hz, configure, set_sys_hz, Clock, Source, XOSC_HZ};
+    use crate::gpio::{is_taken, FunctionPin, Funcsel};
+    use crate::pac::uart::UartlcrHWlen;
+    use crate::pac::{IO_BANK0, PADS_BANK0, UART0};
+    use crate::resets::{is_in_reset, Reset};
+
+    #[test]
+    fn test_baud_divisors() {
+        assert_eq!(baud_divisors(12_000_000, 115_200), (6, 33));
+        assert_eq!(baud_divisors(12_000_000, 9_600), (78, 8));
+        assert_eq!(baud_divisors(150_000_000, 115_200), (81, 24));
+        assert_eq!(baud_divisors(1_000, 115_200), (1, 0));
+    }
+
+    #[test]
+    fn test_uart_init() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        configure(Clock::Peri, Source::Xosc, XOSC_HZ, XOSC_HZ).unwrap();
+
+        unsafe { uart_init(115_200) }.unwrap();
+
+        assert!(!is_in_reset(Reset::Uart0));
+        assert_eq!(IO_BANK0.gpio_ctrl(0).read().funcsel().bits(), Funcsel::Uart as u32);
+        assert_eq!(IO_BANK0.gpio_ctrl(1).read().funcsel().bits(), Funcsel::Uart as u32);
+        let rx = PADS_BANK0.gpio(1).read();
+        assert!(rx.ie() && rx.pue() && !rx.iso());
+        assert_eq!(UART0.uartibrd().read().baud_divint().bits(), 6);
+        assert_eq!(UART0.uartfbrd().read().baud_divfrac().bits(), 33);
+        let lcr_h = UART0.uartlcr_h().read();
+        assert_eq!(lcr_h.wlen().variant(), Some(UartlcrHWlen::_8bits));
+        assert!(lcr_h.fen());
+        assert_eq!(UART0.uartcr().read().bits(), 0x301);
+    }
+
+    #[test]
+    fn test_baud_follows_clk_peri() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        configure(Clock::Peri, Source::Xosc, XOSC_HZ, XOSC_HZ).unwrap();
+        unsafe { uart_init(115_200) }.unwrap();
+        // What the listener does when clk_peri moves to 150 MHz
+        on_clk_peri_change(Clock::Peri, 150_000_000);
+        assert_eq!(UART0.uartibrd().read().baud_divint().bits(), 81);
+        assert_eq!(UART0.uartfbrd().read().baud_divfrac().bits(), 24);
+    }
+
+    #[test]
+    fn test_baud_follows_pll_sys() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        clock_set_reported_hz(Clock::Ref, XOSC_HZ);
+        configure(Clock::Peri, Source::PllSys, 150_000_000, 150_000_000).unwrap();
+        unsafe { uart_init(115_200) }.unwrap();
+
+        // clk_peri runs straight from PLL_SYS, not through clk_sys
+        let solution = unsafe { set_sys_hz(125_000_000) }.unwrap();
+        assert_eq!(clock_get_hz(Clock::Peri), solution.hz);
+        let (int, frac) = baud_divisors(solution.hz, 115_200);
+        assert_eq!(UART0.uartibrd().read().baud_divint().bits() as usize, int);
+        assert_eq!(UART0.uartfbrd().read().baud_divfrac().bits() as usize, frac);
+
+        // Back to the 150 MHz the other tests expect
+        unsafe { set_sys_hz(150_000_000) }.unwrap();
+    }
+
+    #[test]
+    fn test_uart_init_holds_console_pins() {
+        let _clocks = sim::exclusive();
+        sim::reset();
+        configure(Clock::Peri, Source::Xosc, XOSC_HZ, XOSC_HZ).unwrap();
+        unsafe { uart_init(115_200) }.unwrap();
+        assert!(is_taken(0) && is_taken(1));
+        assert!(FunctionPin::claim(1, Funcsel::Uart).is_none());
+
+        // Running it again keeps the pins it already holds
+        unsafe { uart_init(9_600) }.unwrap();
+
+        // Pins held by anyone else are refused
+        CONSOLE_PINS.store(false, Ordering::Relaxed);
+        assert_eq!(unsafe { uart_init(115_200) }, Err(UartError::PinsTaken));
+        CONSOLE_PINS.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_putc_writes_data_register() {
+        sim::reset();
+        putc(b'A');
+        assert_eq!(UART0.uartdr().read().data().bits(), b'A' as u32);
+    }
+}